@@ -1,8 +1,4 @@
 use image::GenericImageView;
-use image::ImageBuffer;
-use image::Luma;
-use image::LumaA;
-use image::Pixel;
 use std::path::Path;
 
 fn main() {
@@ -13,29 +9,15 @@ fn main() {
     println!("Height is {}", height);
     println!("ColorType is {:?}", rgb_img.color());
 
-    let mut rgba_img = ImageBuffer::new(width, height);
-    let mut rg_img = ImageBuffer::new(width, height);
-    let mut r_img = ImageBuffer::new(width, height);
-
-    println!("Converting RGB -> RGBA/RG/R"); // could be optimized
-    for x in 0u32..width {
-        for y in 0u32..height {
-            let pixel = rgb_img.get_pixel(x, y);
-            let pixel_rgba = pixel.to_rgba();
-            let pixel_rg = LumaA::from([pixel_rgba[0], pixel_rgba[1]]);
-            let pixel_r = Luma::from([pixel_rgba[0]]);
-            rgba_img.put_pixel(x, y, pixel_rgba);
-            rg_img.put_pixel(x, y, pixel_rg);
-            r_img.put_pixel(x, y, pixel_r);
-        }
-    }
-
-    let rgba_img = rgba_img.to_vec();
-    let slice = [rgba_img];
+    let rgba_img = astcenc_rs::Image {
+        extents: astcenc_rs::Extents::new(width, height),
+        data: vec![rgb_img.to_rgba8().into_raw()],
+    };
 
-    let rgba_img = astcenc_rs::Image{ extents: astcenc_rs::Extents::new(width, height), data: slice.as_slice() };
+    println!("Converting RGBA -> R/RG/RGBA");
+    let (r_img, rg_img, _) = rgba_img.split_channels();
+    println!("R is {} bytes, RG is {} bytes", r_img.len(), rg_img.len());
 
-    
     let mut ctx = astcenc_rs::Context::default();
     let swz = astcenc_rs::Swizzle::rgba();
 
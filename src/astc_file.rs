@@ -0,0 +1,213 @@
+//! Reading and writing the `.astc` file format used by ARM's `astcenc` command-line tool.
+
+use std::{
+    convert::TryFrom,
+    fs::File,
+    io::{BufWriter, Read, Write},
+    ops::Deref,
+    path::Path,
+};
+
+use crate::{
+    checked_compressed_size, guess_profile, BlockSize, CompressedImage, Context, DataType,
+    DecodedImage, Error, Extents, Image, Swizzle,
+};
+
+/// The magic number at the start of every `.astc` file, stored little-endian.
+const MAGIC: [u8; 4] = [0x13, 0xAB, 0xA1, 0x5C];
+
+fn write_u24(out: &mut [u8], value: u32) {
+    out.copy_from_slice(&value.to_le_bytes()[..3]);
+}
+
+fn read_u24(bytes: &[u8]) -> u32 {
+    u32::from_le_bytes([bytes[0], bytes[1], bytes[2], 0])
+}
+
+impl CompressedImage {
+    /// Write the image as a `.astc` file, which is a 16-byte header storing the block size and
+    /// image dimensions, followed by the compressed blocks. Returns `Error::BadParam` if the
+    /// dimensions don't fit in the header.
+    pub fn write_astc<W: Write>(&self, mut writer: W) -> Result<(), Error> {
+        let Extents { x, y, z } = self.extents;
+        if x >= 1 << 24 || y >= 1 << 24 || z >= 1 << 24 {
+            return Err(Error::BadParam);
+        }
+
+        let block = self.block.extents();
+        let mut header = [0u8; 16];
+        header[..4].copy_from_slice(&MAGIC);
+        header[4] = block.x as u8;
+        header[5] = block.y as u8;
+        header[6] = block.z as u8;
+        write_u24(&mut header[7..10], x);
+        write_u24(&mut header[10..13], y);
+        write_u24(&mut header[13..16], z);
+
+        writer.write_all(&header)?;
+        writer.write_all(&self.data)?;
+
+        Ok(())
+    }
+
+    /// Read an image from a `.astc` file. Returns `Error::BadData` if the header is invalid or
+    /// the file doesn't contain the number of blocks described by the header.
+    pub fn read_astc<R: Read>(mut reader: R) -> Result<Self, Error> {
+        let mut header = [0u8; 16];
+        reader.read_exact(&mut header)?;
+
+        if header[..4] != MAGIC {
+            return Err(Error::BadData);
+        }
+
+        let block = Extents::new_3d(header[4] as u32, header[5] as u32, header[6] as u32);
+        let extents = Extents::new_3d(
+            read_u24(&header[7..10]),
+            read_u24(&header[10..13]),
+            read_u24(&header[13..16]),
+        );
+
+        let block = BlockSize::try_from(block).map_err(|_| Error::BadData)?;
+
+        let mut data = Vec::new();
+        reader.read_to_end(&mut data)?;
+
+        if data.len() != checked_compressed_size(block, extents).ok_or(Error::BadData)? {
+            return Err(Error::BadData);
+        }
+
+        Ok(Self {
+            block,
+            extents,
+            data,
+        })
+    }
+
+    /// Decompress the image to the component type implied by its blocks, see
+    /// `Context::decompress_auto`. A `.astc` header doesn't store the profile, so it is guessed
+    /// with `guess_profile`. Returns `Error::BadData` if the blocks are invalid.
+    pub fn decode(&self, swizzle: Swizzle) -> Result<DecodedImage, Error> {
+        let profile = guess_profile(&self.data, self.block, self.extents).ok_or(Error::BadData)?;
+        Context::decompress_auto(&self.data, self.block, self.extents, profile, swizzle)
+    }
+}
+
+#[cfg(feature = "base64")]
+impl CompressedImage {
+    /// Encode the image as a `.astc` file (see `CompressedImage::write_astc`) in standard base64,
+    /// for embedding small textures in JSON or other text formats. Enabled with the `base64`
+    /// feature.
+    pub fn to_base64(&self) -> Result<String, Error> {
+        use base64::Engine;
+
+        let mut file = Vec::with_capacity(16 + self.data.len());
+        self.write_astc(&mut file)?;
+
+        Ok(base64::engine::general_purpose::STANDARD.encode(file))
+    }
+
+    /// Decode an image encoded with `CompressedImage::to_base64`. Returns `Error::BadData` if the
+    /// string isn't valid base64 or doesn't hold a valid `.astc` file. Enabled with the `base64`
+    /// feature.
+    pub fn from_base64(encoded: &str) -> Result<Self, Error> {
+        use base64::Engine;
+
+        let file = base64::engine::general_purpose::STANDARD
+            .decode(encoded)
+            .map_err(|_| Error::BadData)?;
+
+        Self::read_astc(&file[..]).map_err(|error| match error {
+            Error::Io(std::io::ErrorKind::UnexpectedEof) => Error::BadData,
+            error => error,
+        })
+    }
+}
+
+impl Context {
+    /// Compress the given image and save it as a `.astc` file at `path`, which can be read back
+    /// with `CompressedImage::read_astc` or loaded by ARM's `astcenc` tool.
+    pub fn compress_to_astc_file<D, T, L, P>(
+        &mut self,
+        image: &Image<T>,
+        swizzle: Swizzle,
+        path: P,
+    ) -> Result<(), Error>
+    where
+        D: DataType,
+        T: Deref<Target = [L]>,
+        L: Deref<Target = [D]>,
+        P: AsRef<Path>,
+    {
+        let compressed = CompressedImage {
+            block: self.block_size(),
+            extents: image.extents,
+            data: self.compress(image, swizzle)?,
+        };
+
+        let mut writer = BufWriter::new(File::create(path)?);
+        compressed.write_astc(&mut writer)?;
+        writer.flush()?;
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs::File;
+
+    use crate::{BlockSize, CompressedImage, Context, Extents, Swizzle};
+
+    #[test]
+    fn compress_to_astc_file_round_trips() {
+        let extents = Extents::new(30, 20);
+        let img = crate::tests::random_rgba8(extents);
+
+        let path = crate::tests::temp_path("compress-to-astc-file.astc");
+
+        let mut ctx = Context::default();
+        let swz = Swizzle::rgba();
+        ctx.compress_to_astc_file(&img, swz, &path).unwrap();
+
+        let file = CompressedImage::read_astc(File::open(&path).unwrap()).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(file.block, BlockSize::default());
+        assert_eq!(file.extents, extents);
+        assert_eq!(file.data, ctx.compress(&img, swz).unwrap());
+
+        let decoded = ctx.decompress::<u8>(&file.data, file.extents, swz).unwrap();
+        assert_eq!(decoded.extents, extents);
+
+        match file.decode(swz).unwrap() {
+            crate::DecodedImage::U8(auto) => assert_eq!(auto.data, decoded.data),
+            _ => panic!("LDR file didn't decode to u8"),
+        }
+    }
+
+    #[cfg(feature = "base64")]
+    #[test]
+    fn base64_round_trips() {
+        let extents = Extents::new(12, 8);
+        let img = crate::tests::random_rgba8(extents);
+
+        let compressed = CompressedImage {
+            block: BlockSize::default(),
+            extents,
+            data: Context::default().compress(&img, Swizzle::rgba()).unwrap(),
+        };
+
+        let encoded = compressed.to_base64().unwrap();
+        assert!(encoded.is_ascii());
+        assert_eq!(CompressedImage::from_base64(&encoded).unwrap(), compressed);
+
+        assert_eq!(
+            CompressedImage::from_base64("not base64!"),
+            Err(crate::Error::BadData)
+        );
+        assert_eq!(
+            CompressedImage::from_base64(&encoded[..8]),
+            Err(crate::Error::BadData)
+        );
+    }
+}
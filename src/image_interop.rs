@@ -0,0 +1,161 @@
+//! Conversions from the `image` crate's buffer types, enabled with the `image` feature.
+
+use std::path::Path;
+
+use crate::{Context, Error, Extents, Image, Swizzle};
+
+fn image_error_to_error(error: image::ImageError) -> Error {
+    match error {
+        image::ImageError::IoError(error) => Error::from(error),
+        image::ImageError::Unsupported(_) => Error::NotImplemented,
+        _ => Error::Unknown,
+    }
+}
+
+/// Convert any 8-bit buffer to RGBA. Greyscale is copied to the red, green and blue components,
+/// and buffers without alpha get an alpha of 255.
+impl<P: image::Pixel<Subpixel = u8>> From<&image::ImageBuffer<P, Vec<u8>>> for Image<Vec<Vec<u8>>> {
+    fn from(buffer: &image::ImageBuffer<P, Vec<u8>>) -> Self {
+        Self {
+            extents: Extents::new(buffer.width(), buffer.height()),
+            data: vec![buffer
+                .pixels()
+                .flat_map(|pixel| pixel.to_rgba().0)
+                .collect()],
+        }
+    }
+}
+
+impl From<&image::Rgba32FImage> for Image<Vec<Vec<f32>>> {
+    fn from(buffer: &image::Rgba32FImage) -> Self {
+        Self {
+            extents: Extents::new(buffer.width(), buffer.height()),
+            data: vec![buffer.as_raw().clone()],
+        }
+    }
+}
+
+impl Context {
+    /// Compress an image loaded through the `image` crate. 32-bit float images (for example
+    /// those loaded from EXR files) are compressed as `f32`, which is required for the HDR
+    /// profiles, and everything else is converted to 8-bit RGBA.
+    pub fn compress_dynamic(
+        &mut self,
+        image: &image::DynamicImage,
+        swizzle: Swizzle,
+    ) -> Result<Vec<u8>, Error> {
+        match image {
+            image::DynamicImage::ImageRgba32F(buffer) => {
+                self.compress(&Image::from(buffer), swizzle)
+            }
+            image::DynamicImage::ImageRgb32F(_) => {
+                self.compress(&Image::from(&image.to_rgba32f()), swizzle)
+            }
+            image::DynamicImage::ImageLuma8(buffer) => self.compress(&Image::from(buffer), swizzle),
+            image::DynamicImage::ImageLumaA8(buffer) => {
+                self.compress(&Image::from(buffer), swizzle)
+            }
+            image::DynamicImage::ImageRgb8(buffer) => self.compress(&Image::from(buffer), swizzle),
+            image::DynamicImage::ImageRgba8(buffer) => self.compress(&Image::from(buffer), swizzle),
+            _ => self.compress(&Image::from(&image.to_rgba8()), swizzle),
+        }
+    }
+
+    /// Decompress an image to 8-bit RGBA and save it as a PNG file at `path`, for previewing
+    /// compressed data. Returns `Error::BadParam` for 3D images, since PNG can only store 2D
+    /// images.
+    pub fn decompress_to_png<P: AsRef<Path>>(
+        &mut self,
+        data: &[u8],
+        extents: Extents,
+        swizzle: Swizzle,
+        path: P,
+    ) -> Result<(), Error> {
+        if extents.z != 1 {
+            return Err(Error::BadParam);
+        }
+
+        let mut layers = self.decompress::<u8>(data, extents, swizzle)?.data;
+        let layer = layers.pop().ok_or(Error::Unknown)?;
+
+        image::RgbaImage::from_raw(extents.x, extents.y, layer)
+            .ok_or(Error::Unknown)?
+            .save_with_format(path, image::ImageFormat::Png)
+            .map_err(image_error_to_error)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{ConfigBuilder, Context, Extents, Image, Profile, Swizzle};
+
+    #[test]
+    fn rgba32f_compresses_as_hdr() {
+        let buffer = image::Rgba32FImage::from_fn(16, 16, |x, y| {
+            image::Rgba([x as f32 / 4.0, y as f32 / 4.0, 2.0, 1.0])
+        });
+
+        let img = Image::from(&buffer);
+        assert_eq!(img.extents, Extents::new(16, 16));
+        assert_eq!(img.data[0].len(), 16 * 16 * 4);
+
+        let mut ctx = Context::new(
+            ConfigBuilder::new()
+                .with_profile(Profile::HdrRgba)
+                .build()
+                .unwrap(),
+        )
+        .unwrap();
+        let swz = Swizzle::rgba();
+
+        let data = ctx.compress(&img, swz).unwrap();
+        assert_eq!(data.len(), 4 * 4 * 16);
+
+        let dynamic = ctx
+            .compress_dynamic(&image::DynamicImage::ImageRgba32F(buffer), swz)
+            .unwrap();
+        assert_eq!(data, dynamic);
+    }
+
+    #[test]
+    fn u8_buffers_convert_to_rgba() {
+        let gray = image::GrayImage::from_fn(3, 2, |x, y| image::Luma([(x * 10 + y) as u8]));
+        let img = Image::from(&gray);
+        assert_eq!(img.extents, Extents::new(3, 2));
+        assert_eq!(img.data[0][4 * 4..4 * 5], [11, 11, 11, 255]);
+
+        let gray_alpha = image::GrayAlphaImage::from_pixel(3, 2, image::LumaA([7, 9]));
+        let img = Image::from(&gray_alpha);
+        assert_eq!(img.data[0], [7, 7, 7, 9].repeat(6));
+
+        let rgb = image::RgbImage::from_pixel(3, 2, image::Rgb([1, 2, 3]));
+        let img = Image::from(&rgb);
+        assert_eq!(img.data[0], [1, 2, 3, 255].repeat(6));
+
+        let rgba = image::RgbaImage::from_fn(3, 2, |x, y| image::Rgba([x as u8, y as u8, 5, 6]));
+        let img = Image::from(&rgba);
+        assert_eq!(img.data[0], *rgba.as_raw());
+    }
+
+    #[test]
+    fn decompress_to_png_writes_image() {
+        let extents = Extents::new(20, 12);
+        let img = crate::tests::random_rgba8(extents);
+
+        let mut ctx = Context::default();
+        let swz = Swizzle::rgba();
+        let data = ctx.compress(&img, swz).unwrap();
+
+        let path = crate::tests::temp_path("decompress-to-png.png");
+        ctx.decompress_to_png(&data, extents, swz, &path).unwrap();
+
+        let png = image::open(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!((png.width(), png.height()), (extents.x, extents.y));
+
+        assert_eq!(
+            ctx.decompress_to_png(&data, Extents::new_3d(20, 12, 2), swz, &path),
+            Err(crate::Error::BadParam)
+        );
+    }
+}
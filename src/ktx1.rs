@@ -0,0 +1,215 @@
+//! Reading ASTC textures from KTX1 files, enabled with the `ktx` feature.
+
+use std::io::{self, Read};
+
+use crate::{
+    checked_compressed_size, BlockSize, Error, Extents, Profile, BLOCK_SIZES_2D, BLOCK_SIZES_3D,
+};
+
+/// The 12-byte identifier at the start of every KTX1 file.
+const IDENTIFIER: [u8; 12] = [
+    0xAB, 0x4B, 0x54, 0x58, 0x20, 0x31, 0x31, 0xBB, 0x0D, 0x0A, 0x1A, 0x0A,
+];
+
+const GL_COMPRESSED_RGBA_ASTC_4X4: u32 = 0x93B0;
+const GL_COMPRESSED_RGBA_ASTC_3X3X3: u32 = 0x93C0;
+const GL_COMPRESSED_SRGB8_ALPHA8_ASTC_4X4: u32 = 0x93D0;
+const GL_COMPRESSED_SRGB8_ALPHA8_ASTC_3X3X3: u32 = 0x93E0;
+
+/// The base level of an ASTC texture read from a KTX1 file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Texture {
+    /// The block size the data was compressed with.
+    pub block: BlockSize,
+    /// The dimensions of the image.
+    pub extents: Extents,
+    /// `Profile::LdrSrgb` for the sRGB formats, otherwise `Profile::LdrRgba`. GL uses the same
+    /// formats for LDR and HDR data, so HDR textures are reported as `Profile::LdrRgba` too.
+    pub profile: Profile,
+    /// The compressed blocks, ready to be passed to `Context::decompress`.
+    pub data: Vec<u8>,
+}
+
+/// Find the block size and profile of an ASTC GL internal format.
+fn from_gl_format(format: u32) -> Option<(BlockSize, Profile)> {
+    let (base, profile) = match format {
+        GL_COMPRESSED_RGBA_ASTC_4X4..=0x93BD => (GL_COMPRESSED_RGBA_ASTC_4X4, Profile::LdrRgba),
+        GL_COMPRESSED_RGBA_ASTC_3X3X3..=0x93C9 => (GL_COMPRESSED_RGBA_ASTC_3X3X3, Profile::LdrRgba),
+        GL_COMPRESSED_SRGB8_ALPHA8_ASTC_4X4..=0x93DD => {
+            (GL_COMPRESSED_SRGB8_ALPHA8_ASTC_4X4, Profile::LdrSrgb)
+        }
+        GL_COMPRESSED_SRGB8_ALPHA8_ASTC_3X3X3..=0x93E9 => {
+            (GL_COMPRESSED_SRGB8_ALPHA8_ASTC_3X3X3, Profile::LdrSrgb)
+        }
+        _ => return None,
+    };
+
+    let index = (format - base) as usize;
+    let block = match base {
+        GL_COMPRESSED_RGBA_ASTC_4X4 | GL_COMPRESSED_SRGB8_ALPHA8_ASTC_4X4 => {
+            let (x, y) = BLOCK_SIZES_2D[index];
+            Extents::new(x, y)
+        }
+        _ => {
+            let (x, y, z) = BLOCK_SIZES_3D[index];
+            Extents::new_3d(x, y, z)
+        }
+    };
+
+    Some((BlockSize(block), profile))
+}
+
+/// Read the base level of an ASTC texture from a KTX1 file. For cubemaps only the first face
+/// is returned, and array textures are not supported. Returns `Error::BadData` if the file is
+/// not a KTX1 file or does not contain ASTC data.
+pub fn read<R: Read>(mut reader: R) -> Result<Texture, Error> {
+    let mut header = [0u8; 64];
+    reader.read_exact(&mut header)?;
+
+    if header[..12] != IDENTIFIER {
+        return Err(Error::BadData);
+    }
+
+    let swap = match u32::from_le_bytes([header[12], header[13], header[14], header[15]]) {
+        0x04030201 => false,
+        0x01020304 => true,
+        _ => return Err(Error::BadData),
+    };
+    let decode = |bytes: [u8; 4]| {
+        if swap {
+            u32::from_be_bytes(bytes)
+        } else {
+            u32::from_le_bytes(bytes)
+        }
+    };
+    let field = |index: usize| {
+        let start = 16 + index * 4;
+        decode([
+            header[start],
+            header[start + 1],
+            header[start + 2],
+            header[start + 3],
+        ])
+    };
+
+    let gl_type = field(0);
+    let gl_format = field(2);
+    let gl_internal_format = field(3);
+    let width = field(5);
+    let height = field(6).max(1);
+    let depth = field(7).max(1);
+    let array_elements = field(8);
+    let key_value_bytes = field(11);
+
+    if gl_type != 0 || gl_format != 0 {
+        return Err(Error::BadData);
+    }
+
+    if array_elements != 0 {
+        return Err(Error::NotImplemented);
+    }
+
+    let (block, profile) = from_gl_format(gl_internal_format).ok_or(Error::BadData)?;
+    let extents = Extents::new_3d(width, height, depth);
+
+    io::copy(
+        &mut reader.by_ref().take(key_value_bytes as u64),
+        &mut io::sink(),
+    )?;
+
+    let mut image_size = [0u8; 4];
+    reader.read_exact(&mut image_size)?;
+    let image_size = decode(image_size) as usize;
+
+    if image_size != checked_compressed_size(block, extents).ok_or(Error::BadData)? {
+        return Err(Error::BadData);
+    }
+
+    let mut data = vec![0; image_size];
+    reader.read_exact(&mut data)?;
+
+    Ok(Texture {
+        block,
+        extents,
+        profile,
+        data,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use crate::{BlockSize, ConfigBuilder, Context, Error, Extents, Image, Profile, Swizzle};
+
+    fn ktx1_file(internal_format: u32, extents: Extents, data: &[u8]) -> Vec<u8> {
+        let key_value = b"\x08\x00\x00\x00key\x00val\x00";
+
+        let mut file = super::IDENTIFIER.to_vec();
+        for field in [
+            0x04030201,
+            0,
+            1,
+            0,
+            internal_format,
+            0x1908,
+            extents.x,
+            extents.y,
+            0,
+            0,
+            1,
+            1,
+            key_value.len() as u32,
+        ] {
+            file.extend_from_slice(&u32::to_le_bytes(field));
+        }
+        file.extend_from_slice(key_value);
+        file.extend_from_slice(&(data.len() as u32).to_le_bytes());
+        file.extend_from_slice(data);
+        file
+    }
+
+    #[test]
+    fn read_decodes_payload() {
+        let extents = Extents::new(40, 24);
+        let img = Image {
+            extents,
+            data: vec![(0..extents.x * extents.y * 4)
+                .map(|i| (i % 251) as u8)
+                .collect::<Vec<u8>>()],
+        };
+
+        let block = BlockSize::new_2d(8, 6).unwrap();
+        let mut ctx =
+            Context::new(ConfigBuilder::new().with_block_size(block).build().unwrap()).unwrap();
+        let swz = Swizzle::rgba();
+        let data = ctx.compress(&img, swz).unwrap();
+
+        // GL_COMPRESSED_SRGB8_ALPHA8_ASTC_8x6_KHR
+        let file = ktx1_file(0x93D6, extents, &data);
+        let texture = super::read(Cursor::new(file)).unwrap();
+
+        assert_eq!(texture.block, block);
+        assert_eq!(texture.extents, extents);
+        assert_eq!(texture.profile, Profile::LdrSrgb);
+        assert_eq!(texture.data, data);
+
+        let decoded = ctx
+            .decompress::<u8>(&texture.data, texture.extents, swz)
+            .unwrap();
+        assert_eq!(decoded.data[0].len(), img.data[0].len());
+    }
+
+    #[test]
+    fn read_rejects_bad_identifier() {
+        let mut file = ktx1_file(0x93B0, Extents::new(4, 4), &[0; 16]);
+        file[1] = b'X';
+        assert_eq!(super::read(Cursor::new(file)), Err(Error::BadData));
+    }
+
+    #[test]
+    fn read_rejects_overflowing_extents() {
+        let file = ktx1_file(0x93B0, Extents::new(u32::MAX, u32::MAX), &[0; 16]);
+        assert_eq!(super::read(Cursor::new(file)), Err(Error::BadData));
+    }
+}
@@ -0,0 +1,369 @@
+//! Reading and writing ASTC textures in KTX2 files, enabled with the `ktx` feature.
+
+use std::io::{Read, Write};
+
+use crate::{
+    checked_compressed_size, compressed_size, from_vk_format, mip_extents, mip_level_count,
+    vk_format, BlockSize, Context, DecodedImage, Error, Extents, MipChain, Profile, Swizzle,
+};
+
+/// The 12-byte identifier at the start of every KTX2 file.
+const IDENTIFIER: [u8; 12] = [
+    0xAB, 0x4B, 0x54, 0x58, 0x20, 0x32, 0x30, 0xBB, 0x0D, 0x0A, 0x1A, 0x0A,
+];
+
+/// The size of the header and the index that follows it, before the level index.
+const HEADER_LEN: usize = 80;
+/// The size of one entry of the level index.
+const LEVEL_INDEX_ENTRY_LEN: usize = 24;
+
+const KHR_DF_MODEL_ASTC: u32 = 162;
+const KHR_DF_PRIMARIES_BT709: u32 = 1;
+const KHR_DF_TRANSFER_LINEAR: u32 = 1;
+const KHR_DF_TRANSFER_SRGB: u32 = 2;
+const KHR_DF_SAMPLE_DATATYPE_SIGNED: u32 = 0x40;
+const KHR_DF_SAMPLE_DATATYPE_FLOAT: u32 = 0x80;
+
+/// The base level of an ASTC texture read from a KTX2 file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Texture {
+    /// The block size the data was compressed with.
+    pub block: BlockSize,
+    /// The dimensions of the image.
+    pub extents: Extents,
+    /// `Profile::LdrRgba`, `Profile::LdrSrgb` or `Profile::HdrRgba`, depending on whether the
+    /// `VkFormat` is a `UNORM`, `SRGB` or `SFLOAT` format.
+    pub profile: Profile,
+    /// The compressed blocks, ready to be passed to `Context::decompress`.
+    pub data: Vec<u8>,
+}
+
+impl Texture {
+    /// Decompress the texture to the component type implied by its format, see
+    /// `Context::decompress_auto`.
+    pub fn decode(&self, swizzle: Swizzle) -> Result<DecodedImage, Error> {
+        Context::decompress_auto(&self.data, self.block, self.extents, self.profile, swizzle)
+    }
+}
+
+/// Read the base level of an ASTC texture from a KTX2 file. Returns `Error::BadData` if the file
+/// is not a KTX2 file or does not contain ASTC data, and `Error::NotImplemented` for array
+/// textures, cubemaps and supercompressed files.
+pub fn read<R: Read>(mut reader: R) -> Result<Texture, Error> {
+    let mut file = Vec::new();
+    reader.read_to_end(&mut file)?;
+
+    if file.len() < HEADER_LEN + LEVEL_INDEX_ENTRY_LEN || file[..12] != IDENTIFIER {
+        return Err(Error::BadData);
+    }
+
+    let field = |offset: usize| {
+        u32::from_le_bytes([
+            file[offset],
+            file[offset + 1],
+            file[offset + 2],
+            file[offset + 3],
+        ])
+    };
+    let field64 = |offset: usize| field(offset) as u64 | (field(offset + 4) as u64) << 32;
+
+    let format = field(12);
+    let width = field(20);
+    let height = field(24).max(1);
+    let depth = field(28).max(1);
+    let layer_count = field(32);
+    let face_count = field(36);
+    let supercompression_scheme = field(44);
+
+    if layer_count > 1 || face_count != 1 || supercompression_scheme != 0 {
+        return Err(Error::NotImplemented);
+    }
+
+    let (profile, block) = from_vk_format(format).ok_or(Error::BadData)?;
+    let extents = Extents::new_3d(width, height, depth);
+
+    // The base level is always the first entry of the level index.
+    let offset = field64(HEADER_LEN);
+    let length = field64(HEADER_LEN + 8);
+    if length != checked_compressed_size(block, extents).ok_or(Error::BadData)? as u64
+        || offset
+            .checked_add(length)
+            .map_or(true, |end| end > file.len() as u64)
+    {
+        return Err(Error::BadData);
+    }
+
+    let offset = offset as usize;
+    Ok(Texture {
+        block,
+        extents,
+        profile,
+        data: file[offset..offset + length as usize].to_vec(),
+    })
+}
+
+/// The data format descriptor for ASTC data, which is a single basic descriptor block with one
+/// sample covering the whole 128-bit block.
+fn data_format_descriptor(block: Extents, profile: Profile) -> Vec<u8> {
+    const BLOCK_LEN: u32 = 24 + 16;
+
+    let transfer = match profile {
+        Profile::LdrSrgb => KHR_DF_TRANSFER_SRGB,
+        _ => KHR_DF_TRANSFER_LINEAR,
+    };
+    let (qualifiers, lower, upper) = if profile.requires_float_input() {
+        (
+            KHR_DF_SAMPLE_DATATYPE_SIGNED | KHR_DF_SAMPLE_DATATYPE_FLOAT,
+            (-1.0f32).to_bits(),
+            1.0f32.to_bits(),
+        )
+    } else {
+        (0, 0, u32::MAX)
+    };
+
+    let words = [
+        4 + BLOCK_LEN,
+        // Khronos vendor and basic descriptor type.
+        0,
+        2 | BLOCK_LEN << 16,
+        KHR_DF_MODEL_ASTC | KHR_DF_PRIMARIES_BT709 << 8 | transfer << 16,
+        (block.x - 1) | (block.y - 1) << 8 | (block.z - 1) << 16,
+        Extents::block_byte_cost() as u32,
+        0,
+        // A single sample with a bit length of 128 and the ASTC data channel.
+        127 << 16 | qualifiers << 24,
+        0,
+        lower,
+        upper,
+    ];
+
+    words.iter().flat_map(|word| word.to_le_bytes()).collect()
+}
+
+/// Write a single-level ASTC texture as a KTX2 file, see `write_mips`.
+pub fn write<W: Write>(
+    writer: W,
+    block: BlockSize,
+    extents: Extents,
+    profile: Profile,
+    data: &[u8],
+) -> Result<(), Error> {
+    write_mips(
+        writer,
+        block,
+        extents,
+        profile,
+        &MipChain {
+            levels: vec![data.to_vec()],
+        },
+    )
+}
+
+/// Write a mip chain of ASTC data as a KTX2 file, with the `VkFormat` matching the profile and
+/// block size. The HDR profiles use the `SFLOAT` formats. Returns `Error::BadParam` if the chain
+/// is empty, has more levels than a full chain for `base_extents`, or any level isn't the size of
+/// a compressed image with the halved extents of that level, and `Error::NotImplemented` for 3D
+/// block sizes, which have no `VkFormat`.
+pub fn write_mips<W: Write>(
+    mut writer: W,
+    block: BlockSize,
+    base_extents: Extents,
+    profile: Profile,
+    chain: &MipChain,
+) -> Result<(), Error> {
+    let level_count = chain.levels.len();
+    if level_count == 0 || level_count > mip_level_count(base_extents) as usize {
+        return Err(Error::BadParam);
+    }
+
+    for (level, data) in chain.levels.iter().enumerate() {
+        if data.len() != compressed_size(block, mip_extents(base_extents, level as u32)) {
+            return Err(Error::BadParam);
+        }
+    }
+
+    let format = vk_format(profile, block).ok_or(Error::NotImplemented)?;
+    let dfd = data_format_descriptor(block.extents(), profile);
+    let dfd_offset = HEADER_LEN + level_count * LEVEL_INDEX_ENTRY_LEN;
+
+    // Levels are stored smallest first, each aligned to the 16-byte block size. Every level is a
+    // whole number of blocks, so only the first one needs padding.
+    let data_offset = (dfd_offset + dfd.len() + 15) / 16 * 16;
+    let mut offsets = vec![0; level_count];
+    let mut offset = data_offset;
+    for level in (0..level_count).rev() {
+        offsets[level] = offset;
+        offset += chain.levels[level].len();
+    }
+
+    let mut header = IDENTIFIER.to_vec();
+    for field in [
+        format,
+        // typeSize, which is 1 for block-compressed formats.
+        1,
+        base_extents.x,
+        base_extents.y,
+        if base_extents.z > 1 {
+            base_extents.z
+        } else {
+            0
+        },
+        // layerCount and faceCount.
+        0,
+        1,
+        level_count as u32,
+        // supercompressionScheme.
+        0,
+        dfd_offset as u32,
+        dfd.len() as u32,
+        // No key/value data.
+        0,
+        0,
+    ] {
+        header.extend_from_slice(&field.to_le_bytes());
+    }
+    // No supercompression global data.
+    header.extend_from_slice(&[0; 16]);
+
+    for (level, data) in chain.levels.iter().enumerate() {
+        for field in [offsets[level], data.len(), data.len()] {
+            header.extend_from_slice(&(field as u64).to_le_bytes());
+        }
+    }
+
+    header.extend_from_slice(&dfd);
+    header.resize(data_offset, 0);
+
+    writer.write_all(&header)?;
+    for data in chain.levels.iter().rev() {
+        writer.write_all(data)?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use crate::{
+        compressed_size, BlockSize, ConfigBuilder, Context, DecodedImage, Error, Extents, Image,
+        Profile, Swizzle,
+    };
+
+    fn read_u32(file: &[u8], offset: usize) -> u32 {
+        u32::from_le_bytes([
+            file[offset],
+            file[offset + 1],
+            file[offset + 2],
+            file[offset + 3],
+        ])
+    }
+
+    fn read_u64(file: &[u8], offset: usize) -> usize {
+        read_u32(file, offset) as usize | (read_u32(file, offset + 4) as usize) << 32
+    }
+
+    #[test]
+    fn write_mips_stores_every_level() {
+        let extents = Extents::new(24, 16);
+        let img = crate::tests::random_rgba8(extents);
+
+        let block = BlockSize::default();
+        let mut ctx = Context::default();
+        let mut chain = ctx.compress_mips(&img, Swizzle::rgba()).unwrap();
+        chain.levels.truncate(2);
+
+        let mut file = Vec::new();
+        super::write_mips(&mut file, block, extents, Profile::LdrSrgb, &chain).unwrap();
+
+        assert_eq!(file[..12], super::IDENTIFIER);
+        // VK_FORMAT_ASTC_4x4_SRGB_BLOCK
+        assert_eq!(read_u32(&file, 12), 158);
+        assert_eq!(read_u32(&file, 20), 24);
+        assert_eq!(read_u32(&file, 24), 16);
+        assert_eq!(read_u32(&file, 40), 2);
+
+        let sizes = [
+            compressed_size(block, Extents::new(24, 16)),
+            compressed_size(block, Extents::new(12, 8)),
+        ];
+        for (level, &size) in sizes.iter().enumerate() {
+            let entry = super::HEADER_LEN + level * super::LEVEL_INDEX_ENTRY_LEN;
+            let offset = read_u64(&file, entry);
+            assert_eq!(read_u64(&file, entry + 8), size);
+            assert_eq!(read_u64(&file, entry + 16), size);
+            assert_eq!(offset % 16, 0);
+            assert_eq!(file[offset..offset + size], chain.levels[level]);
+        }
+
+        // The smallest level comes first.
+        assert!(
+            read_u64(&file, super::HEADER_LEN + super::LEVEL_INDEX_ENTRY_LEN)
+                < read_u64(&file, super::HEADER_LEN)
+        );
+        assert_eq!(file.len(), read_u64(&file, super::HEADER_LEN) + sizes[0]);
+
+        chain.levels.swap(0, 1);
+        assert_eq!(
+            super::write_mips(Vec::new(), block, extents, Profile::LdrSrgb, &chain),
+            Err(Error::BadParam)
+        );
+    }
+
+    #[test]
+    fn read_decodes_to_the_format_type() {
+        let extents = Extents::new(20, 12);
+        let block = BlockSize::default();
+        let swz = Swizzle::rgba();
+
+        let ldr = crate::tests::random_rgba8(extents);
+        let ldr_data = Context::default().compress(&ldr, swz).unwrap();
+
+        let mut file = Vec::new();
+        super::write(&mut file, block, extents, Profile::LdrSrgb, &ldr_data).unwrap();
+        let texture = super::read(Cursor::new(file)).unwrap();
+        assert_eq!(texture.block, block);
+        assert_eq!(texture.extents, extents);
+        assert_eq!(texture.profile, Profile::LdrSrgb);
+        assert_eq!(texture.data, ldr_data);
+
+        match texture.decode(swz).unwrap() {
+            DecodedImage::U8(image) => assert_eq!(image.extents, extents),
+            _ => panic!("LDR texture didn't decode to u8"),
+        }
+
+        let hdr = Image {
+            extents,
+            data: vec![(0..extents.x * extents.y * 4)
+                .map(|i| i as f32 / 64.0)
+                .collect::<Vec<f32>>()],
+        };
+        let hdr_data = Context::new(
+            ConfigBuilder::new()
+                .with_profile(Profile::HdrRgba)
+                .build()
+                .unwrap(),
+        )
+        .unwrap()
+        .compress(&hdr, swz)
+        .unwrap();
+
+        let mut file = Vec::new();
+        super::write(&mut file, block, extents, Profile::HdrRgba, &hdr_data).unwrap();
+        let texture = super::read(Cursor::new(file)).unwrap();
+        assert_eq!(texture.profile, Profile::HdrRgba);
+
+        let decoded = texture.decode(swz).unwrap();
+        assert_eq!(decoded.data_type(), crate::Type::F16);
+        match decoded {
+            DecodedImage::F16(image) => assert_eq!(image.extents, extents),
+            _ => panic!("HDR texture didn't decode to f16"),
+        }
+
+        let mut file = Vec::new();
+        super::write(&mut file, block, extents, Profile::LdrRgba, &ldr_data).unwrap();
+        file[1] = b'X';
+        assert_eq!(super::read(Cursor::new(file)), Err(Error::BadData));
+    }
+}
@@ -6,18 +6,77 @@
 //! In order to use the images generated by this library directly on the GPU, you need ensure that
 //! the GPU you're running on has support for ASTC, which can be queried with the Vulkan
 //! `textureCompressionASTC_*` flags (one flag for each of the modes in `Profile`).
+//!
+//! ## Instruction sets
+//!
+//! The SIMD instruction set used by the encoder (SSE, AVX2, NEON or none) is fixed when
+//! `astcenc-sys` builds the encoder library, and the library does not offer a way to switch
+//! between instruction sets at runtime. To compare instruction sets, or to work around a
+//! build that targets an instruction set the running CPU doesn't support, rebuild
+//! `astcenc-sys` for a different target instead.
 
 #![warn(missing_docs)]
 
 use std::{
+    collections::HashMap,
+    convert::TryFrom,
+    io::{Read, Write},
+    marker::PhantomData,
     mem::MaybeUninit,
-    ops::{Deref, DerefMut},
+    ops::{Deref, DerefMut, Range},
     os::raw::c_void,
     ptr::NonNull,
 };
 
-/// An error during initialization, compression or decompression.
+use sha256::Sha256;
+
+mod astc_file;
+#[cfg(feature = "image")]
+mod image_interop;
+#[cfg(feature = "ktx")]
+pub mod ktx1;
+#[cfg(feature = "ktx")]
+pub mod ktx2;
+mod sha256;
+#[cfg(feature = "test-utils")]
+pub mod test_utils;
+
+/// The 2D block sizes supported by ASTC, in the order used by the GL and Vulkan format enums.
+const BLOCK_SIZES_2D: [(u32, u32); 14] = [
+    (4, 4),
+    (5, 4),
+    (5, 5),
+    (6, 5),
+    (6, 6),
+    (8, 5),
+    (8, 6),
+    (8, 8),
+    (10, 5),
+    (10, 6),
+    (10, 8),
+    (10, 10),
+    (12, 10),
+    (12, 12),
+];
+
+/// The 3D block sizes supported by ASTC, in the order used by the GL format enums.
+const BLOCK_SIZES_3D: [(u32, u32, u32); 10] = [
+    (3, 3, 3),
+    (4, 3, 3),
+    (4, 4, 3),
+    (4, 4, 4),
+    (5, 4, 4),
+    (5, 5, 4),
+    (5, 5, 5),
+    (6, 5, 5),
+    (6, 6, 5),
+    (6, 6, 6),
+];
+
+/// An error during initialization, compression or decompression. More variants may be added in
+/// the future, so matches on it need a wildcard arm.
 #[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+#[non_exhaustive]
 pub enum Error {
     /// The block size is out of range of the supported sizes.
     BadBlockSize,
@@ -41,10 +100,20 @@ pub enum Error {
     NotImplemented,
     /// We ran out of memory
     OutOfMem,
+    /// The compressed data, or the file containing it, is malformed
+    BadData,
+    /// Reading or writing a file failed
+    Io(std::io::ErrorKind),
     /// Something else went wrong (this should never happen!)
     Unknown,
 }
 
+impl From<std::io::Error> for Error {
+    fn from(error: std::io::Error) -> Self {
+        Self::Io(error.kind())
+    }
+}
+
 fn error_code_to_result(code: astcenc_sys::astcenc_error) -> Result<(), Error> {
     match code {
         astcenc_sys::astcenc_error_ASTCENC_SUCCESS => Ok(()),
@@ -67,14 +136,109 @@ fn error_code_to_result(code: astcenc_sys::astcenc_error) -> Result<(), Error> {
 pub struct Context {
     inner: NonNull<astcenc_sys::astcenc_context>,
     config: Config,
+    cache: CompressCache,
+}
+
+/// A SHA-256 digest of everything the output of `Context::compress_cached` depends on, apart
+/// from the config: the extents, component type, swizzle and the bytes of every layer. The image
+/// is hashed in place, so no copy of it is kept or made.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+struct CacheKey([u8; 32]);
+
+impl CacheKey {
+    fn new<D, T, L>(image: &Image<T>, swizzle: Swizzle) -> Self
+    where
+        D: DataType,
+        T: Deref<Target = [L]>,
+        L: Deref<Target = [D]>,
+    {
+        let mut hash = Sha256::new();
+        hash.update(&image.extents.to_le_bytes());
+        hash.update(&[
+            D::TYPE as u8,
+            swizzle.r as u8,
+            swizzle.g as u8,
+            swizzle.b as u8,
+            swizzle.a as u8,
+        ]);
+        for layer in image.data.iter() {
+            let layer = D::as_u8s(layer);
+            hash.update(&(layer.len() as u64).to_le_bytes());
+            hash.update(layer);
+        }
+
+        Self(hash.finish())
+    }
+}
+
+/// The results of `Context::compress_cached`. When it holds too many entries or too many bytes of
+/// compressed data, the least recently used entries are evicted.
+#[derive(Default)]
+struct CompressCache {
+    entries: HashMap<CacheKey, (u64, Vec<u8>)>,
+    /// The total length of the compressed data of all entries.
+    bytes: usize,
+    /// Incremented on every lookup, and stored with an entry when it is used.
+    clock: u64,
+    hits: u64,
+}
+
+impl CompressCache {
+    const CAPACITY: usize = 32;
+    const MAX_BYTES: usize = 64 << 20;
+
+    fn get(&mut self, key: &CacheKey) -> Option<Vec<u8>> {
+        self.clock += 1;
+        let clock = self.clock;
+
+        let (last_used, data) = self.entries.get_mut(key)?;
+        *last_used = clock;
+        self.hits += 1;
+        Some(data.clone())
+    }
+
+    fn insert(&mut self, key: CacheKey, data: Vec<u8>) {
+        if data.len() > Self::MAX_BYTES {
+            return;
+        }
+
+        if let Some((_, old)) = self.entries.remove(&key) {
+            self.bytes -= old.len();
+        }
+
+        while self.entries.len() >= Self::CAPACITY || self.bytes + data.len() > Self::MAX_BYTES {
+            let oldest = self
+                .entries
+                .iter()
+                .min_by_key(|(_, &(last_used, _))| last_used)
+                .map(|(&key, _)| key);
+            let oldest = match oldest {
+                Some(oldest) => oldest,
+                None => break,
+            };
+
+            if let Some((_, evicted)) = self.entries.remove(&oldest) {
+                self.bytes -= evicted.len();
+            }
+        }
+
+        self.bytes += data.len();
+        self.entries.insert(key, (self.clock, data));
+    }
 }
 
 unsafe impl Sync for Context {}
 unsafe impl Send for Context {}
 
+/// Creates a context with the default config.
+///
+/// # Panics
+///
+/// Panics if the context could not be allocated, see `Context::try_default` for a version that
+/// returns an error instead.
 impl Default for Context {
     fn default() -> Self {
-        Self::new(Config::default()).unwrap()
+        Self::try_default().unwrap()
     }
 }
 
@@ -97,6 +261,12 @@ impl Extents {
         Self::new(4, 4)
     }
 
+    /// The number of bytes used by a single compressed block. This is the same for every block
+    /// size, which is why larger blocks give a higher compression ratio.
+    pub const fn block_byte_cost() -> usize {
+        16
+    }
+
     /// Create a 2D extent (depth set to 1)
     pub fn new(x: u32, y: u32) -> Self {
         Self { x, y, z: 1 }
@@ -106,6 +276,128 @@ impl Extents {
     pub fn new_3d(x: u32, y: u32, z: u32) -> Self {
         Self { x, y, z }
     }
+
+    /// Serialize the extents as the width, height and depth in that order, each as a
+    /// little-endian `u32`. Use this when writing the extents into custom file headers, so the
+    /// encoding is the same on every platform.
+    pub fn to_le_bytes(self) -> [u8; 12] {
+        let mut bytes = [0; 12];
+        bytes[0..4].copy_from_slice(&self.x.to_le_bytes());
+        bytes[4..8].copy_from_slice(&self.y.to_le_bytes());
+        bytes[8..12].copy_from_slice(&self.z.to_le_bytes());
+        bytes
+    }
+
+    /// Deserialize extents written by `Extents::to_le_bytes`.
+    pub fn from_le_bytes(bytes: &[u8; 12]) -> Self {
+        let field = |start: usize| {
+            u32::from_le_bytes([
+                bytes[start],
+                bytes[start + 1],
+                bytes[start + 2],
+                bytes[start + 3],
+            ])
+        };
+        Self::new_3d(field(0), field(4), field(8))
+    }
+
+    /// The number of blocks of size `block` needed to cover these extents in each dimension.
+    fn in_blocks(self, block: Extents) -> Self {
+        // Rounds up without overflowing for dimensions close to `u32::MAX`.
+        let blocks = |size: u32, block: u32| size / block + (size % block != 0) as u32;
+        Self::new_3d(
+            blocks(self.x, block.x),
+            blocks(self.y, block.y),
+            blocks(self.z, block.z),
+        )
+    }
+}
+
+/// A block size that is one of the footprints supported by ASTC. Unlike `Extents`, which is
+/// also used for image dimensions, a `BlockSize` can only be constructed for a legal block size,
+/// so passing it to `ConfigBuilder::block_size` can't fail with `Error::BadBlockSize`.
+///
+/// The two types don't convert implicitly, so image dimensions can't be passed where a block
+/// size is expected:
+///
+/// ```compile_fail
+/// let mut builder = astcenc_rs::ConfigBuilder::new();
+/// builder.block_size(astcenc_rs::Extents::new(1920, 1080));
+/// ```
+///
+/// and a block size can't be used as the dimensions of an image:
+///
+/// ```compile_fail
+/// let image = astcenc_rs::Image {
+///     extents: astcenc_rs::BlockSize::default(),
+///     data: vec![vec![0u8; 4 * 4 * 4]],
+/// };
+/// ```
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub struct BlockSize(Extents);
+
+impl BlockSize {
+    /// Create a 2D block size. Returns `Error::BadBlockSize` if `x` by `y` isn't one of the 2D
+    /// footprints supported by ASTC.
+    pub fn new_2d(x: u32, y: u32) -> Result<Self, Error> {
+        if !BLOCK_SIZES_2D.contains(&(x, y)) {
+            return Err(Error::BadBlockSize);
+        }
+
+        Ok(Self(Extents::new(x, y)))
+    }
+
+    /// Create a 3D block size. Returns `Error::BadBlockSize` if `x` by `y` by `z` isn't one of
+    /// the 3D footprints supported by ASTC. A depth of 1 is accepted for the 2D footprints.
+    pub fn new_3d(x: u32, y: u32, z: u32) -> Result<Self, Error> {
+        if z == 1 {
+            return Self::new_2d(x, y);
+        }
+
+        if !BLOCK_SIZES_3D.contains(&(x, y, z)) {
+            return Err(Error::BadBlockSize);
+        }
+
+        Ok(Self(Extents::new_3d(x, y, z)))
+    }
+
+    /// The width, height and depth of the block.
+    pub fn extents(self) -> Extents {
+        self.0
+    }
+
+    /// The number of blocks of this size needed to cover an image with the given extents in
+    /// each dimension. Blocks are stored in x-major, then y, then z order.
+    pub fn grid(self, image: Extents) -> Extents {
+        image.in_blocks(self.0)
+    }
+
+    /// The size in bytes of an image with the given extents compressed with this block size, see
+    /// `compressed_size`.
+    pub fn compressed_size(self, image: Extents) -> usize {
+        compressed_size(self, image)
+    }
+}
+
+/// The default block size, 4x4x1, see `Extents::default_block_size`.
+impl Default for BlockSize {
+    fn default() -> Self {
+        Self(Extents::default_block_size())
+    }
+}
+
+impl TryFrom<Extents> for BlockSize {
+    type Error = Error;
+
+    fn try_from(block: Extents) -> Result<Self, Error> {
+        Self::new_3d(block.x, block.y, block.z)
+    }
+}
+
+impl From<BlockSize> for Extents {
+    fn from(block: BlockSize) -> Self {
+        block.0
+    }
 }
 
 /// The performance preset, higher settings take more time but provide higher quality.
@@ -133,7 +425,8 @@ pub const PRESET_VERY_THOROUGH: Preset = Preset(astcenc_sys::ASTCENC_PRE_VERYTHO
 /// The exhaustive, highest quality, search preset.
 pub const PRESET_EXHAUSTIVE: Preset = Preset(astcenc_sys::ASTCENC_PRE_EXHAUSTIVE);
 
-/// The color profile. HDR and LDR SRGB require the image to use floats for its individual colors.
+/// The color profile. HDR profiles require the image to use floats for its individual colors, see
+/// `Profile::requires_float_input`.
 #[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
 pub enum Profile {
     /// HDR in all 4 components.
@@ -153,6 +446,12 @@ impl Default for Profile {
 }
 
 impl Profile {
+    /// Whether images compressed with this profile need to use a floating-point `Type`. This is
+    /// the case for the HDR profiles, since `Type::U8` can't represent values outside `0..=1`.
+    pub fn requires_float_input(&self) -> bool {
+        !supported_types(*self).contains(&Type::U8)
+    }
+
     fn into_sys(self) -> astcenc_sys::astcenc_profile {
         match self {
             Self::HdrRgba => astcenc_sys::astcenc_profile_ASTCENC_PRF_HDR,
@@ -163,9 +462,103 @@ impl Profile {
     }
 }
 
+/// The kind of content stored in a texture, used to pick recommended encoder settings with
+/// `ConfigBuilder::optimize_for`.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum TextureKind {
+    /// Color data, such as diffuse or albedo maps. Uses the sRGB profile.
+    Albedo,
+    /// Tangent-space normal maps, compressed with the `rrrg` swizzle.
+    NormalMap,
+    /// Independent masks packed into the components, with the alpha treated as the most important.
+    Mask,
+    /// HDR color data, such as environment maps.
+    Hdr,
+}
+
 /// Configuration for initializing `Context`, see `ConfigBuilder` for more information.
 pub struct Config {
     inner: astcenc_sys::astcenc_config,
+    builder: ConfigBuilder,
+}
+
+impl Config {
+    fn block_size(&self) -> Extents {
+        Extents::new_3d(self.inner.block_x, self.inner.block_y, self.inner.block_z)
+    }
+
+    /// A single number for ranking configs by the quality they are likely to produce, where
+    /// higher is better (and slower). It combines the bit rate of the block size with the search
+    /// effort of the tuning parameters chosen by the preset.
+    ///
+    /// This is a heuristic for comparing configs in tooling and not a measurement: the actual
+    /// quality and speed depend heavily on the image being compressed.
+    pub fn tradeoff_score(&self) -> f32 {
+        let block = self.block_size();
+        let bits_per_texel = 128.0 / (block.x * block.y * block.z) as f32;
+
+        let effort = (self.inner.tune_block_mode_limit as f32 / 100.0)
+            * self.inner.tune_partition_count_limit as f32
+            * self.inner.tune_candidate_limit as f32
+            * self.inner.tune_refinement_limit as f32;
+
+        bits_per_texel * (1.0 + effort).ln()
+    }
+
+    /// The names and values of the `tune_*` search parameters of the underlying `astcenc`
+    /// config, as resolved from the preset and any overrides in the builder. Integer limits are
+    /// converted to `f32`. This is intended for tooling that shows the effective settings of a
+    /// preset, for example as the starting point for customizing it.
+    pub fn active_tuning_fields(&self) -> Vec<(&'static str, f32)> {
+        let inner = &self.inner;
+        vec![
+            (
+                "tune_partition_count_limit",
+                inner.tune_partition_count_limit as f32,
+            ),
+            (
+                "tune_2partition_index_limit",
+                inner.tune_2partition_index_limit as f32,
+            ),
+            (
+                "tune_3partition_index_limit",
+                inner.tune_3partition_index_limit as f32,
+            ),
+            (
+                "tune_4partition_index_limit",
+                inner.tune_4partition_index_limit as f32,
+            ),
+            ("tune_block_mode_limit", inner.tune_block_mode_limit as f32),
+            ("tune_refinement_limit", inner.tune_refinement_limit as f32),
+            ("tune_candidate_limit", inner.tune_candidate_limit as f32),
+            (
+                "tune_2partitioning_candidate_limit",
+                inner.tune_2partitioning_candidate_limit as f32,
+            ),
+            (
+                "tune_3partitioning_candidate_limit",
+                inner.tune_3partitioning_candidate_limit as f32,
+            ),
+            (
+                "tune_4partitioning_candidate_limit",
+                inner.tune_4partitioning_candidate_limit as f32,
+            ),
+            ("tune_db_limit", inner.tune_db_limit),
+            ("tune_mse_overshoot", inner.tune_mse_overshoot),
+            (
+                "tune_2partition_early_out_limit_factor",
+                inner.tune_2partition_early_out_limit_factor,
+            ),
+            (
+                "tune_3partition_early_out_limit_factor",
+                inner.tune_3partition_early_out_limit_factor,
+            ),
+            (
+                "tune_2plane_early_out_limit_correlation",
+                inner.tune_2plane_early_out_limit_correlation,
+            ),
+        ]
+    }
 }
 
 impl Default for Config {
@@ -174,12 +567,27 @@ impl Default for Config {
     }
 }
 
+/// Build the config with `ConfigBuilder::build`.
+impl TryFrom<ConfigBuilder> for Config {
+    type Error = Error;
+
+    fn try_from(builder: ConfigBuilder) -> Result<Self, Error> {
+        builder.build()
+    }
+}
+
 /// Builder for the context configuration.
-#[derive(Clone)]
+#[derive(Clone, PartialEq)]
 pub struct ConfigBuilder {
     profile: Profile,
     preset: Preset,
-    block_size: Extents,
+    block_size: BlockSize,
+    flags: Flags,
+    channel_weights: Option<[f32; 4]>,
+    alpha_cutoff: Option<f32>,
+    refinement_iterations: Option<u32>,
+    decorrelate_alpha: bool,
+    plane_separation_threshold: Option<f32>,
 }
 
 impl Default for ConfigBuilder {
@@ -187,7 +595,13 @@ impl Default for ConfigBuilder {
         Self {
             profile: Profile::default(),
             preset: Preset::default(),
-            block_size: Extents::default_block_size(),
+            block_size: BlockSize::default(),
+            flags: Flags::default(),
+            channel_weights: None,
+            alpha_cutoff: None,
+            refinement_iterations: None,
+            decorrelate_alpha: false,
+            plane_separation_threshold: None,
         }
     }
 }
@@ -227,37 +641,234 @@ impl ConfigBuilder {
     }
 
     /// Set the block size, which decides the compression ratio for the image. Each block
-    /// uses 16 bytes of memory.
-    pub fn block_size(&mut self, block_size: Extents) -> &mut Self {
+    /// uses `Extents::block_byte_cost()` bytes of memory.
+    pub fn block_size(&mut self, block_size: BlockSize) -> &mut Self {
         self.block_size = block_size;
         self
     }
 
     /// Set the block size, which decides the compression ratio for the image. Each block
-    /// uses 16 bytes of memory.
-    pub fn with_block_size(mut self, block_size: Extents) -> Self {
+    /// uses `Extents::block_byte_cost()` bytes of memory.
+    pub fn with_block_size(mut self, block_size: BlockSize) -> Self {
         self.block_size(block_size);
         self
     }
 
+    /// Set the flags, which control what the context can be used for and how the encoder
+    /// calculates error. Defaults to `Flags::USE_ALPHA_WEIGHT`.
+    pub fn flags(&mut self, flags: Flags) -> &mut Self {
+        self.flags = flags;
+        self
+    }
+
+    /// Set the flags, which control what the context can be used for and how the encoder
+    /// calculates error. Defaults to `Flags::USE_ALPHA_WEIGHT`.
+    pub fn with_flags(mut self, flags: Flags) -> Self {
+        self.flags(flags);
+        self
+    }
+
+    /// Clear `Flags::USE_ALPHA_WEIGHT`, which is set by default, while keeping the other flags.
+    /// Weighting the color error by the alpha only helps images with varying alpha, so for opaque
+    /// textures this lets the encoder spend its effort on the color instead.
+    pub fn no_alpha_weight(&mut self) -> &mut Self {
+        self.flags.remove(Flags::USE_ALPHA_WEIGHT);
+        self
+    }
+
+    /// Clear `Flags::USE_ALPHA_WEIGHT`, see `ConfigBuilder::no_alpha_weight`.
+    pub fn with_no_alpha_weight(mut self) -> Self {
+        self.no_alpha_weight();
+        self
+    }
+
+    /// Set the relative weight of the error in each of the R, G, B and A components. By default
+    /// the weights are chosen by the encoder based on the flags.
+    pub fn channel_weights(&mut self, weights: [f32; 4]) -> &mut Self {
+        self.channel_weights = Some(weights);
+        self
+    }
+
+    /// Set the relative weight of the error in each of the R, G, B and A components. By default
+    /// the weights are chosen by the encoder based on the flags.
+    pub fn with_channel_weights(mut self, weights: [f32; 4]) -> Self {
+        self.channel_weights(weights);
+        self
+    }
+
+    /// Mark the texture as a cutout texture whose alpha is tested against `cutoff` (in `0..=1`),
+    /// such as foliage. The encoder has no notion of a threshold, so the cutoff itself is only
+    /// validated: the effect is that alpha error is weighted 4 times as heavily as the channel
+    /// weights would otherwise give it, which keeps alpha close to its original value, and so on
+    /// the same side of the cutoff, at the expense of color accuracy.
+    ///
+    /// `Flags::USE_ALPHA_WEIGHT` (set by default) additionally scales color error by alpha, so
+    /// with both the color accuracy lost is mostly in the transparent areas, where it doesn't
+    /// matter. Without it, the color accuracy is lost evenly across the texture.
+    ///
+    /// `ConfigBuilder::build` returns `Error::BadParam` if `cutoff` is outside `0..=1`.
+    pub fn alpha_cutoff(&mut self, cutoff: f32) -> &mut Self {
+        self.alpha_cutoff = Some(cutoff);
+        self
+    }
+
+    /// Mark the texture as a cutout texture whose alpha is tested against `cutoff`, see
+    /// `ConfigBuilder::alpha_cutoff`.
+    pub fn with_alpha_cutoff(mut self, cutoff: f32) -> Self {
+        self.alpha_cutoff(cutoff);
+        self
+    }
+
+    /// Override the number of refinement passes the encoder runs on each candidate encoding,
+    /// which is otherwise chosen by the preset. Each pass nudges the endpoint colors and weights
+    /// of a candidate towards a lower error, so more passes give slightly higher quality at the
+    /// margin for a roughly proportional increase in time per candidate. Unlike the block mode
+    /// limit, which decides how many encodings are tried, this only affects how hard each of
+    /// them is optimized.
+    pub fn refinement_iterations(&mut self, iterations: u32) -> &mut Self {
+        self.refinement_iterations = Some(iterations);
+        self
+    }
+
+    /// Override the number of refinement passes the encoder runs on each candidate encoding,
+    /// see `ConfigBuilder::refinement_iterations`.
+    pub fn with_refinement_iterations(mut self, iterations: u32) -> Self {
+        self.refinement_iterations(iterations);
+        self
+    }
+
+    /// Treat the alpha as unrelated to the color, for textures that pack a mask or other data
+    /// into the alpha. When set, the alpha isn't used to weight the color error (overriding
+    /// `Flags::USE_ALPHA_WEIGHT`), and the encoder always tries encodings that store a separate
+    /// set of weights for one component, so the alpha can vary independently of the color.
+    pub fn decorrelate_alpha(&mut self, decorrelate: bool) -> &mut Self {
+        self.decorrelate_alpha = decorrelate;
+        self
+    }
+
+    /// Treat the alpha as unrelated to the color, see `ConfigBuilder::decorrelate_alpha`.
+    pub fn with_decorrelate_alpha(mut self, decorrelate: bool) -> Self {
+        self.decorrelate_alpha(decorrelate);
+        self
+    }
+
+    /// Set how readily the encoder stores one component in its own plane of weights (a dual-plane
+    /// encoding), from `0.0` to `1.0`. The encoder only tries dual-plane encodings for components
+    /// whose correlation with the others is below `1.0 - threshold`, so lower values try them
+    /// for more blocks, at the cost of compression time. Defaults to a value picked by the
+    /// preset, and is ignored when `ConfigBuilder::decorrelate_alpha` is set. `build` returns
+    /// `Error::BadParam` if the threshold is outside `0.0..=1.0`.
+    pub fn plane_separation_threshold(&mut self, threshold: f32) -> &mut Self {
+        self.plane_separation_threshold = Some(threshold);
+        self
+    }
+
+    /// Set how readily the encoder stores one component in its own plane of weights, see
+    /// `ConfigBuilder::plane_separation_threshold`.
+    pub fn with_plane_separation_threshold(mut self, threshold: f32) -> Self {
+        self.plane_separation_threshold(threshold);
+        self
+    }
+
+    /// Set the profile, flags and channel weights to the recommended combination for the given
+    /// kind of texture. Any of these can still be overridden afterwards.
+    pub fn optimize_for(&mut self, kind: TextureKind) -> &mut Self {
+        match kind {
+            TextureKind::Albedo => {
+                self.profile = Profile::LdrSrgb;
+                self.flags = Flags::USE_ALPHA_WEIGHT;
+                self.channel_weights = None;
+            }
+            TextureKind::NormalMap => {
+                // Normal maps are expected to be compressed with the `rrrg` swizzle, so only the
+                // red and alpha components carry data.
+                self.profile = Profile::LdrRgba;
+                self.flags = Flags::MAP_NORMAL | Flags::USE_PERCEPTUAL;
+                self.channel_weights = Some([1.0, 0.0, 0.0, 1.0]);
+            }
+            TextureKind::Mask => {
+                // The components of a mask are unrelated, so the alpha must not be used to weight
+                // the others, and is usually the most important of them.
+                self.profile = Profile::LdrRgba;
+                self.flags = Flags::empty();
+                self.channel_weights = Some([1.0, 1.0, 1.0, 2.0]);
+            }
+            TextureKind::Hdr => {
+                self.profile = Profile::HdrRgba;
+                self.flags = Flags::USE_ALPHA_WEIGHT;
+                self.channel_weights = None;
+            }
+        }
+        self
+    }
+
+    /// Set the profile, flags and channel weights to the recommended combination for the given
+    /// kind of texture. Any of these can still be overridden afterwards.
+    pub fn with_optimize_for(mut self, kind: TextureKind) -> Self {
+        self.optimize_for(kind);
+        self
+    }
+
     /// Create the config from these settings.
     pub fn build(self) -> Result<Config, Error> {
         let mut cfg: MaybeUninit<astcenc_sys::astcenc_config> = MaybeUninit::uninit();
 
+        let mut flags = self.flags;
+        if self.decorrelate_alpha {
+            flags.remove(Flags::USE_ALPHA_WEIGHT);
+        }
+
+        let block = self.block_size.extents();
         error_code_to_result(unsafe {
             astcenc_sys::astcenc_config_init(
                 self.profile.into_sys(),
-                self.block_size.x,
-                self.block_size.y,
-                self.block_size.z,
+                block.x,
+                block.y,
+                block.z,
                 self.preset.0,
-                Flags::default().into_sys(),
+                flags.into_sys(),
                 cfg.as_mut_ptr(),
             )
         })?;
 
+        let mut inner = unsafe { cfg.assume_init() };
+
+        if let Some([r, g, b, a]) = self.channel_weights {
+            inner.cw_r_weight = r;
+            inner.cw_g_weight = g;
+            inner.cw_b_weight = b;
+            inner.cw_a_weight = a;
+        }
+
+        if let Some(cutoff) = self.alpha_cutoff {
+            if !(0.0..=1.0).contains(&cutoff) {
+                return Err(Error::BadParam);
+            }
+
+            inner.cw_a_weight *= 4.0;
+        }
+
+        if let Some(iterations) = self.refinement_iterations {
+            inner.tune_refinement_limit = iterations;
+        }
+
+        if let Some(threshold) = self.plane_separation_threshold {
+            if !(0.0..=1.0).contains(&threshold) {
+                return Err(Error::BadParam);
+            }
+
+            inner.tune_2plane_early_out_limit_correlation = 1.0 - threshold;
+        }
+
+        if self.decorrelate_alpha {
+            // Dual-plane encodings are skipped when the components are more correlated than
+            // this limit, which they can never be.
+            inner.tune_2plane_early_out_limit_correlation = 1.0;
+        }
+
         Ok(Config {
-            inner: unsafe { cfg.assume_init() },
+            inner,
+            builder: self,
         })
     }
 }
@@ -275,6 +886,15 @@ pub enum Type {
 }
 
 impl Type {
+    /// The size of a single component of this type, in bytes.
+    pub fn size(self) -> usize {
+        match self {
+            Self::F16 => std::mem::size_of::<half::f16>(),
+            Self::F32 => std::mem::size_of::<f32>(),
+            Self::U8 => std::mem::size_of::<u8>(),
+        }
+    }
+
     fn into_sys(self) -> astcenc_sys::astcenc_type {
         match self {
             Self::F16 => astcenc_sys::astcenc_type_ASTCENC_TYPE_F16,
@@ -284,8 +904,17 @@ impl Type {
     }
 }
 
-/// A valid type for a subpixel.
-pub trait DataType: Sized {
+mod sealed {
+    pub trait Sealed {}
+
+    impl Sealed for u8 {}
+    impl Sealed for f32 {}
+    impl Sealed for half::f16 {}
+}
+
+/// A valid type for a subpixel. This trait is sealed, since `u8`, `f32` and `half::f16` are the
+/// only component types `astcenc` supports.
+pub trait DataType: sealed::Sealed + Sized {
     /// The runtime subpixel type associated with this compile-time type.
     const TYPE: Type;
 
@@ -293,6 +922,12 @@ pub trait DataType: Sized {
     fn as_u8s(array: &[Self]) -> &[u8];
     /// Convert a mutable array of `Self` to bytes.
     fn as_u8s_mut(array: &mut [Self]) -> &mut [u8];
+
+    /// Convert a component to a float, where the LDR range maps to `0.0..=1.0`.
+    fn to_f32(self) -> f32;
+    /// Convert a float to a component, where `0.0..=1.0` maps to the LDR range. Values are
+    /// clamped if they cannot be represented.
+    fn from_f32(value: f32) -> Self;
 }
 
 impl DataType for u8 {
@@ -305,17 +940,40 @@ impl DataType for u8 {
     fn as_u8s_mut(array: &mut [Self]) -> &mut [u8] {
         array
     }
+
+    fn to_f32(self) -> f32 {
+        self as f32 / u8::MAX as f32
+    }
+
+    fn from_f32(value: f32) -> Self {
+        (value.clamp(0.0, 1.0) * u8::MAX as f32).round() as u8
+    }
 }
 
 impl DataType for f32 {
     const TYPE: Type = Type::F32;
 
     fn as_u8s(array: &[Self]) -> &[u8] {
-        unsafe { std::mem::transmute(array) }
+        unsafe {
+            std::slice::from_raw_parts(array.as_ptr() as *const u8, std::mem::size_of_val(array))
+        }
     }
 
     fn as_u8s_mut(array: &mut [Self]) -> &mut [u8] {
-        unsafe { std::mem::transmute(array) }
+        unsafe {
+            std::slice::from_raw_parts_mut(
+                array.as_mut_ptr() as *mut u8,
+                std::mem::size_of_val(array),
+            )
+        }
+    }
+
+    fn to_f32(self) -> f32 {
+        self
+    }
+
+    fn from_f32(value: f32) -> Self {
+        value
     }
 }
 
@@ -323,11 +981,26 @@ impl DataType for half::f16 {
     const TYPE: Type = Type::F16;
 
     fn as_u8s(array: &[Self]) -> &[u8] {
-        unsafe { std::mem::transmute(array) }
+        unsafe {
+            std::slice::from_raw_parts(array.as_ptr() as *const u8, std::mem::size_of_val(array))
+        }
     }
 
     fn as_u8s_mut(array: &mut [Self]) -> &mut [u8] {
-        unsafe { std::mem::transmute(array) }
+        unsafe {
+            std::slice::from_raw_parts_mut(
+                array.as_mut_ptr() as *mut u8,
+                std::mem::size_of_val(array),
+            )
+        }
+    }
+
+    fn to_f32(self) -> f32 {
+        half::f16::to_f32(self)
+    }
+
+    fn from_f32(value: f32) -> Self {
+        half::f16::from_f32(value)
     }
 }
 
@@ -342,359 +1015,3621 @@ pub struct Image<T> {
     pub data: T,
 }
 
-/// An individual component of a swizzle.
-#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
-pub enum Selector {
-    /// Select the red component
-    Red,
-    /// Select the green component
-    Green,
-    /// Select the blue component
-    Blue,
-    /// Select the alpha component
-    Alpha,
-    /// Select the z component, which is calculated using trigonometry based on the red
-    /// and green components.
-    Z,
-    /// Constant 1.
-    One,
-    /// Constant 0.
-    Zero,
+/// A borrowed slice of components in memory owned by foreign code, such as a buffer returned by
+/// a C library, for compressing it without copying. Use one `ForeignSlice` per layer, for
+/// example as an `Image<Vec<ForeignSlice<u8>>>`.
+pub struct ForeignSlice<'a, D> {
+    ptr: *const D,
+    len: usize,
+    _marker: PhantomData<&'a [D]>,
 }
 
-impl Selector {
-    fn into_sys(self) -> astcenc_sys::astcenc_swz {
-        match self {
-            Self::Red => astcenc_sys::astcenc_swz_ASTCENC_SWZ_R,
-            Self::Green => astcenc_sys::astcenc_swz_ASTCENC_SWZ_G,
-            Self::Blue => astcenc_sys::astcenc_swz_ASTCENC_SWZ_B,
-            Self::Alpha => astcenc_sys::astcenc_swz_ASTCENC_SWZ_A,
-            Self::Z => astcenc_sys::astcenc_swz_ASTCENC_SWZ_Z,
-            Self::One => astcenc_sys::astcenc_swz_ASTCENC_SWZ_1,
-            Self::Zero => astcenc_sys::astcenc_swz_ASTCENC_SWZ_0,
+impl<'a, D> ForeignSlice<'a, D> {
+    /// Wrap `len` components starting at `ptr`.
+    ///
+    /// # Safety
+    ///
+    /// The same requirements as `std::slice::from_raw_parts` apply: `ptr` must be non-null,
+    /// aligned and valid for reads of `len` initialized components for the lifetime `'a`, and the
+    /// memory must not be mutated during that lifetime.
+    pub unsafe fn from_raw_parts(ptr: *const D, len: usize) -> Self {
+        Self {
+            ptr,
+            len,
+            _marker: PhantomData,
         }
     }
 }
 
-/// A component selection swizzle. The image must always be in RGBA order, even if the G, B
-/// and/or A components are never used.
-#[derive(Debug, Copy, Clone, PartialEq, Eq)]
-pub struct Swizzle {
-    /// The component to use for the red channel.
-    pub r: Selector,
-    /// The component to use for the green channel.
-    pub g: Selector,
-    /// The component to use for the blue channel.
-    pub b: Selector,
-    /// The component to use for the alpha channel.
-    pub a: Selector,
+impl<'a, D> Deref for ForeignSlice<'a, D> {
+    type Target = [D];
+
+    fn deref(&self) -> &[D] {
+        unsafe { std::slice::from_raw_parts(self.ptr, self.len) }
+    }
 }
 
-impl Swizzle {
-    /// Default swizzle for greyscale without alpha.
-    ///
-    /// To access the output in a shader, use the `.g` swizzle.
-    pub fn rrr1() -> Self {
-        Self {
-            r: Selector::Red,
-            g: Selector::Red,
-            b: Selector::Red,
-            a: Selector::One,
-        }
+unsafe impl<'a, D: Sync> Send for ForeignSlice<'a, D> {}
+unsafe impl<'a, D: Sync> Sync for ForeignSlice<'a, D> {}
+
+impl<T, L, D> Image<T>
+where
+    D: DataType,
+    T: Deref<Target = [L]>,
+    L: Deref<Target = [D]>,
+{
+    /// Create an image, checking that the data has one layer per unit of depth and that each
+    /// layer holds exactly one RGBA texel per pixel. Returns `Error::BadParam` otherwise.
+    pub fn try_new(extents: Extents, data: T) -> Result<Self, Error> {
+        let image = Self { extents, data };
+        image.check_len()?;
+        Ok(image)
     }
 
-    /// Default swizzle for greyscale with alpha.
-    ///
-    /// To access the output in a shader, use the `.ga` swizzle.
-    pub fn rrrg() -> Self {
-        Self {
-            r: Selector::Red,
-            g: Selector::Red,
-            b: Selector::Red,
-            a: Selector::Green,
+    fn check_len(&self) -> Result<(), Error> {
+        if self.data.len() != self.extents.z as usize {
+            return Err(Error::BadParam);
         }
-    }
 
-    /// Default swizzle for RGB without alpha.
-    ///
-    /// To access the output in a shader, use the `.rga` swizzle.
-    pub fn rgb1() -> Self {
-        Self {
-            r: Selector::Red,
-            g: Selector::Green,
-            b: Selector::Blue,
-            a: Selector::One,
+        if self
+            .data
+            .iter()
+            .any(|layer| layer.len() != (self.extents.x * self.extents.y * 4) as usize)
+        {
+            return Err(Error::BadParam);
         }
+
+        Ok(())
     }
+}
 
-    /// Default swizzle for RGB with alpha.
+impl<T, L, D> Image<T>
+where
+    D: DataType + Copy,
+    T: Deref<Target = [L]>,
+    L: Deref<Target = [D]>,
+{
+    /// Scale a 2D image to the given extents, for example to bring an oversized source down to a
+    /// power-of-two size before compressing it. Works for any component type, including `f32`
+    /// for HDR images.
     ///
-    /// To access the output in a shader, use the `.rga` swizzle.
-    pub fn rgba() -> Self {
-        Self {
-            r: Selector::Red,
-            g: Selector::Green,
-            b: Selector::Blue,
-            a: Selector::Alpha,
+    /// This uses a bilinear filter that samples at the texel centers, which is the same as a 2x2
+    /// box filter when halving the image. Shrinking by more than half in one step skips source
+    /// texels and can alias, so for large reductions resize in several steps of at most half.
+    /// Returns `Error::BadParam` if either the image or the new extents are not 2D, or have a
+    /// width or height of zero.
+    pub fn resize(&self, extents: Extents) -> Result<Image<Vec<Vec<D>>>, Error> {
+        self.check_len()?;
+
+        let src = self.extents;
+        if src.z != 1 || extents.z != 1 || [src.x, src.y, extents.x, extents.y].contains(&0) {
+            return Err(Error::BadParam);
         }
-    }
 
-    fn into_sys(self) -> astcenc_sys::astcenc_swizzle {
-        astcenc_sys::astcenc_swizzle {
-            r: self.r.into_sys(),
-            g: self.g.into_sys(),
-            b: self.b.into_sys(),
-            a: self.a.into_sys(),
+        // The two source texels to blend along one axis, and the weight of the second.
+        let sample = |coord: u32, dst_size: u32, src_size: u32| {
+            let pos = ((coord as f32 + 0.5) * src_size as f32 / dst_size as f32 - 0.5).max(0.0);
+            let low = (pos as u32).min(src_size - 1);
+            let high = (low + 1).min(src_size - 1);
+            (low, high, pos - low as f32)
+        };
+
+        let layer = &self.data[0];
+        let texel = |x: u32, y: u32, component: u32| {
+            layer[((y * src.x + x) * 4 + component) as usize].to_f32()
+        };
+
+        let mut data = Vec::with_capacity((extents.x * extents.y * 4) as usize);
+        for y in 0..extents.y {
+            let (y0, y1, fy) = sample(y, extents.y, src.y);
+            for x in 0..extents.x {
+                let (x0, x1, fx) = sample(x, extents.x, src.x);
+                for component in 0..4 {
+                    let top = texel(x0, y0, component) * (1.0 - fx) + texel(x1, y0, component) * fx;
+                    let bottom =
+                        texel(x0, y1, component) * (1.0 - fx) + texel(x1, y1, component) * fx;
+                    data.push(D::from_f32(top * (1.0 - fy) + bottom * fy));
+                }
+            }
         }
+
+        Ok(Image {
+            extents,
+            data: vec![data],
+        })
     }
-}
 
-impl Context {
-    /// Create a new context from the given config (see `ConfigBuilder` for more information on this
-    /// config). Returns an error in the case that the config is invalid or the context could not be
-    /// allocated.
-    pub fn new(config: Config) -> Result<Self, Error> {
-        // TODO: Do this properly somehow
-        const THREADS: usize = 1;
+    /// Copy the image into a new image whose extents are rounded up to a multiple of `block`,
+    /// filling the texels past the right, bottom and back edges as described by `edge`. This is
+    /// the staging buffer used by `Context::compress_edged`, for callers that want to control the
+    /// padding themselves. Returns `Error::BadParam` if the data of the image doesn't match its
+    /// extents.
+    pub fn pad_to_block(
+        &self,
+        block: BlockSize,
+        edge: EdgeMode,
+    ) -> Result<Image<Vec<Vec<D>>>, Error> {
+        self.check_len()?;
 
-        let mut cfg: MaybeUninit<*mut astcenc_sys::astcenc_context> = MaybeUninit::uninit();
+        let src = self.extents;
+        let padded = image_from_block_grid(block.grid(src), block);
 
-        error_code_to_result(unsafe {
-            astcenc_sys::astcenc_context_alloc(&config.inner, THREADS as _, cfg.as_mut_ptr())
-        })?;
+        let source = |coord: u32, size: u32| match edge {
+            EdgeMode::Clamp => Some(coord.min(size - 1)),
+            EdgeMode::Wrap => Some(coord % size),
+            EdgeMode::Transparent => Some(coord).filter(|&coord| coord < size),
+        };
+        let transparent = [D::from_f32(0.0); 4];
 
-        Ok(Self {
-            inner: unsafe { NonNull::new(cfg.assume_init()).ok_or(Error::Unknown)? },
-            config,
+        let data = (0..padded.z)
+            .map(|z| {
+                let mut layer = Vec::with_capacity((padded.x * padded.y * 4) as usize);
+
+                for y in 0..padded.y {
+                    for x in 0..padded.x {
+                        let texel = match (source(x, src.x), source(y, src.y), source(z, src.z)) {
+                            (Some(sx), Some(sy), Some(sz)) => {
+                                let i = ((sy * src.x + sx) * 4) as usize;
+                                &self.data[sz as usize][i..i + 4]
+                            }
+                            _ => &transparent[..],
+                        };
+                        layer.extend_from_slice(texel);
+                    }
+                }
+
+                layer
+            })
+            .collect();
+
+        Ok(Image {
+            extents: padded,
+            data,
         })
     }
 
-    /// Compress the given image, returning a byte vector that can be sent to the GPU.
-    pub fn compress<D, T, L>(
-        &mut self,
-        image: &Image<T>,
-        swizzle: Swizzle,
-    ) -> Result<Vec<u8>, Error>
-    where
-        D: DataType,
-        T: Deref<Target = [L]>,
-        L: Deref<Target = [D]>,
-    {
-        const BYTES_PER_BLOCK: usize = 16;
+    /// The `[min, max]` of each of the R, G, B and A components over the whole image, converted
+    /// with `DataType::to_f32`, for example to set up the exposure for tone-mapping a decoded HDR
+    /// image. For an empty image every range is `[f32::INFINITY, f32::NEG_INFINITY]`.
+    pub fn channel_bounds(&self) -> [[f32; 2]; 4] {
+        let mut bounds = [[f32::INFINITY, f32::NEG_INFINITY]; 4];
 
-        if image.data.len() != image.extents.z as usize {
-            return Err(Error::BadParam);
+        for texel in self.data.iter().flat_map(|layer| layer.chunks_exact(4)) {
+            for (bounds, component) in bounds.iter_mut().zip(texel) {
+                let value = component.to_f32();
+                bounds[0] = bounds[0].min(value);
+                bounds[1] = bounds[1].max(value);
+            }
         }
 
-        if image
-            .data
-            .iter()
-            .any(|layer| layer.len() != (image.extents.x * image.extents.y * 4) as usize)
-        {
-            return Err(Error::BadParam);
+        bounds
+    }
+
+    /// Split the RGBA texels into three separate buffers holding only the red components, the
+    /// red and green components, and all four components, with the layers stored one after
+    /// another. These match the single, dual and four channel inputs of other texture encoders
+    /// (for example BC4, BC5 and BC7), so the same source can be packed for all of them.
+    pub fn split_channels(&self) -> (Vec<D>, Vec<D>, Vec<D>) {
+        let texels = self.data.iter().flat_map(|layer| layer.chunks_exact(4));
+        let count = self.data.iter().map(|layer| layer.len() / 4).sum::<usize>();
+
+        let mut r = Vec::with_capacity(count);
+        let mut rg = Vec::with_capacity(count * 2);
+        let mut rgba = Vec::with_capacity(count * 4);
+
+        for texel in texels {
+            r.push(texel[0]);
+            rg.extend_from_slice(&texel[..2]);
+            rgba.extend_from_slice(texel);
         }
 
-        let blocks_x =
-            (image.extents.x + self.config.inner.block_x - 1) / self.config.inner.block_x;
-        let blocks_y =
-            (image.extents.y + self.config.inner.block_y - 1) / self.config.inner.block_y;
-        let blocks_z =
-            (image.extents.z + self.config.inner.block_z - 1) / self.config.inner.block_z;
+        (r, rg, rgba)
+    }
 
-        let bytes = blocks_x as usize * blocks_y as usize * blocks_z as usize * BYTES_PER_BLOCK;
-        let mut out = Vec::with_capacity(bytes);
+    /// Copy the texels in the box starting at `origin` with the given `size` into a new image.
+    fn region(&self, origin: Extents, size: Extents) -> Image<Vec<Vec<D>>> {
+        let width = self.extents.x as usize;
 
-        let mut image_data_pointers = image
-            .data
-            .iter()
-            .map(|layer| layer.as_ptr() as *const c_void)
-            .collect::<Vec<_>>();
-        let mut image_sys = astcenc_sys::astcenc_image {
-            dim_x: image.extents.x,
-            dim_y: image.extents.y,
-            dim_z: image.extents.z,
-            data_type: D::TYPE.into_sys(),
-            data: image_data_pointers.as_mut_ptr() as *mut *mut _,
-        };
+        Image {
+            extents: size,
+            data: self.data[origin.z as usize..(origin.z + size.z) as usize]
+                .iter()
+                .map(|layer| {
+                    (origin.y..origin.y + size.y)
+                        .flat_map(move |y| {
+                            let start = (y as usize * width + origin.x as usize) * 4;
+                            layer[start..start + size.x as usize * 4].iter().copied()
+                        })
+                        .collect()
+                })
+                .collect(),
+        }
+    }
 
-        error_code_to_result(unsafe {
-            astcenc_sys::astcenc_compress_image(
-                self.inner.as_mut(),
-                &mut image_sys as *mut _,
-                &swizzle.into_sys(),
-                out.as_mut_ptr(),
-                bytes,
-                0,
-            )
-        })?;
+    /// Halve the image in every dimension (down to a minimum of 1) using a box filter, as used
+    /// to generate the next level of a mip chain.
+    fn downsampled(&self) -> Image<Vec<Vec<D>>> {
+        let src = self.extents;
+        let extents = Extents::new_3d((src.x / 2).max(1), (src.y / 2).max(1), (src.z / 2).max(1));
+        let footprint = |coord: u32, size: u32| coord * 2..(coord * 2 + 2).min(size);
 
-        unsafe { out.set_len(bytes) };
+        let data = (0..extents.z)
+            .map(|z| {
+                let mut layer = Vec::with_capacity((extents.x * extents.y * 4) as usize);
 
-        self.reset()?;
+                for y in 0..extents.y {
+                    for x in 0..extents.x {
+                        let mut sum = [0.0f32; 4];
+                        let mut count = 0.0;
 
-        Ok(out)
-    }
+                        for sz in footprint(z, src.z) {
+                            for sy in footprint(y, src.y) {
+                                for sx in footprint(x, src.x) {
+                                    let i = ((sy * src.x + sx) * 4) as usize;
+                                    let texel = &self.data[sz as usize][i..i + 4];
+                                    for (sum, component) in sum.iter_mut().zip(texel) {
+                                        *sum += component.to_f32();
+                                    }
+                                    count += 1.0;
+                                }
+                            }
+                        }
 
-    /// Decompress an image into a pre-existing buffer. The metadata (size and border padding) must
-    /// already be set and enough space must be reserved in `out.data` for the output pixels (RGBA).
-    pub fn decompress_into<D, T, L>(
-        &mut self,
-        data: &[u8],
-        out: &mut Image<T>,
-        swizzle: Swizzle,
-    ) -> Result<(), Error>
-    where
-        D: DataType,
-        T: DerefMut<Target = [L]>,
-        L: DerefMut<Target = [D]>,
-    {
-        let mut image_data_pointers = out
-            .data
-            .iter_mut()
-            .map(|layer| layer.as_mut_ptr() as *mut c_void)
-            .collect::<Vec<_>>();
-        let mut image_sys = astcenc_sys::astcenc_image {
-            dim_x: out.extents.x,
-            dim_y: out.extents.y,
-            dim_z: out.extents.z,
-            data_type: D::TYPE.into_sys(),
-            data: image_data_pointers.as_mut_ptr(),
-        };
+                        layer.extend(sum.iter().map(|&sum| D::from_f32(sum / count)));
+                    }
+                }
 
-        error_code_to_result(unsafe {
-            astcenc_sys::astcenc_decompress_image(
-                self.inner.as_mut(),
-                data.as_ptr(),
-                data.len(),
-                &mut image_sys,
-                &swizzle.into_sys(),
-                0,
-            )
-        })
+                layer
+            })
+            .collect();
+
+        Image { extents, data }
     }
 
-    /// Decompress an image. The metadata is not stored in the compressed data itself, and should be
-    /// stored as a separate header.
-    pub fn decompress<D>(
-        &mut self,
-        data: &[u8],
-        extents: Extents,
-        swizzle: Swizzle,
-    ) -> Result<Image<Vec<Vec<D>>>, Error>
+    /// Copy the image, replacing each RGBA texel with the result of `f`.
+    fn map_texels<F>(&self, mut f: F) -> Image<Vec<Vec<D>>>
     where
-        D: DataType,
+        F: FnMut([D; 4]) -> [D; 4],
     {
-        let size_2d = (extents.x * extents.y * 4) as usize;
-        let mut out = Image {
-            extents,
-            data: (0..extents.z)
-                .map(|_| Vec::with_capacity(size_2d))
-                .collect::<Vec<Vec<D>>>(),
-        };
+        Image {
+            extents: self.extents,
+            data: self
+                .data
+                .iter()
+                .map(|layer| {
+                    layer
+                        .chunks_exact(4)
+                        .flat_map(|texel| f([texel[0], texel[1], texel[2], texel[3]]))
+                        .collect()
+                })
+                .collect(),
+        }
+    }
+}
 
-        let mut image_data_pointers = out
-            .data
-            .iter_mut()
-            .map(|layer| layer.as_mut_ptr() as *mut c_void)
-            .collect::<Vec<_>>();
-        let mut image_sys = astcenc_sys::astcenc_image {
-            dim_x: out.extents.x,
-            dim_y: out.extents.y,
-            dim_z: out.extents.z,
-            data_type: D::TYPE.into_sys(),
-            data: image_data_pointers.as_mut_ptr(),
-        };
+impl<D> Image<Vec<Vec<D>>>
+where
+    D: DataType + Copy,
+{
+    /// Read an image with the given extents from raw RGBA data, with components in native byte
+    /// order and layers stored one after another. Exactly as many bytes as the image needs are
+    /// read. `ty` is the component type of the data, and must match `D`. Returns
+    /// `Error::BadParam` if `ty` doesn't match or if the reader ends before the image is complete.
+    pub fn from_reader<R: Read>(mut reader: R, extents: Extents, ty: Type) -> Result<Self, Error> {
+        if ty != D::TYPE {
+            return Err(Error::BadParam);
+        }
 
-        error_code_to_result(unsafe {
-            astcenc_sys::astcenc_decompress_image(
-                self.inner.as_mut(),
-                data.as_ptr(),
-                data.len(),
-                &mut image_sys,
-                &swizzle.into_sys(),
-                0,
-            )
-        })?;
+        let layer_len = extents.x as usize * extents.y as usize * 4;
 
-        for layer in &mut out.data {
-            unsafe { layer.set_len(size_2d) };
-        }
+        let data = (0..extents.z)
+            .map(|_| {
+                let mut layer = vec![D::from_f32(0.0); layer_len];
+                reader
+                    .read_exact(D::as_u8s_mut(&mut layer))
+                    .map_err(|error| match error.kind() {
+                        std::io::ErrorKind::UnexpectedEof => Error::BadParam,
+                        _ => Error::from(error),
+                    })?;
+                Ok::<_, Error>(layer)
+            })
+            .collect::<Result<Vec<_>, _>>()?;
 
-        Ok(out)
+        Ok(Self { extents, data })
     }
+}
 
-    fn reset(&mut self) -> Result<(), Error> {
-        error_code_to_result(unsafe { astcenc_sys::astcenc_compress_reset(self.inner.as_mut()) })
+impl<T, L> Image<T>
+where
+    T: Deref<Target = [L]>,
+    L: Deref<Target = [half::f16]>,
+{
+    /// Convert a half-float image to single-precision floats, which is lossless.
+    pub fn to_f32(&self) -> Image<Vec<Vec<f32>>> {
+        Image {
+            extents: self.extents,
+            data: self
+                .data
+                .iter()
+                .map(|layer| layer.iter().map(|c| c.to_f32()).collect())
+                .collect(),
+        }
     }
 }
 
-bitflags::bitflags! {
-    /// Configuration flags for the context.
-    pub struct Flags: std::os::raw::c_uint {
-        /// Disable compression support.
-        const DECOMPRESS_ONLY  = astcenc_sys::ASTCENC_FLG_DECOMPRESS_ONLY;
-        /// Treat the image as a 2-component normal map for the purposes of error calculation.
-        /// Z will always be recalculated.
-        const MAP_NORMAL       = astcenc_sys::ASTCENC_FLG_MAP_NORMAL;
-        /// Weight any error in the RGB components by the A component, which leads to better
-        /// quality in areas with higher alpha by comparison.
-        const USE_ALPHA_WEIGHT = astcenc_sys::ASTCENC_FLG_USE_ALPHA_WEIGHT;
-        /// Calculate error using a perceptual algorithm instead of peak signal-to-noise ratio,
-        /// best used for normal maps. Not all input types support perceptual error calculation
-        /// at all.
-        const USE_PERCEPTUAL   = astcenc_sys::ASTCENC_FLG_USE_PERCEPTUAL;
+impl<T, L> Image<T>
+where
+    T: Deref<Target = [L]>,
+    L: Deref<Target = [f32]>,
+{
+    /// Convert a single-precision float image to half-floats, rounding each component to the
+    /// nearest representable value. Values too large for a half-float become infinity.
+    pub fn to_f16(&self) -> Image<Vec<Vec<half::f16>>> {
+        Image {
+            extents: self.extents,
+            data: self
+                .data
+                .iter()
+                .map(|layer| layer.iter().map(|&c| half::f16::from_f32(c)).collect())
+                .collect(),
+        }
     }
 }
 
-impl Flags {
-    fn into_sys(self) -> std::os::raw::c_uint {
-        self.bits()
-    }
+/// A decoded image whose component type was picked from the profile of the compressed data,
+/// see `Context::decompress_auto`.
+pub enum DecodedImage {
+    /// 8-bit components, used for the LDR profiles.
+    U8(Image<Vec<Vec<u8>>>),
+    /// Half-float components, used for the HDR profiles. ASTC stores HDR values with 16-bit
+    /// precision, so this is lossless.
+    F16(Image<Vec<Vec<half::f16>>>),
+    /// 32-bit float components.
+    F32(Image<Vec<Vec<f32>>>),
 }
 
-impl Default for Flags {
-    fn default() -> Self {
-        Flags::USE_ALPHA_WEIGHT
+impl DecodedImage {
+    /// The dimensions of the image.
+    pub fn extents(&self) -> Extents {
+        match self {
+            Self::U8(image) => image.extents,
+            Self::F16(image) => image.extents,
+            Self::F32(image) => image.extents,
+        }
+    }
+
+    /// The component type of the image.
+    pub fn data_type(&self) -> Type {
+        match self {
+            Self::U8(_) => Type::U8,
+            Self::F16(_) => Type::F16,
+            Self::F32(_) => Type::F32,
+        }
     }
 }
 
-#[cfg(test)]
-mod tests {
-    #[test]
-    fn basic_works() {
-        let mut img = super::Image::<Vec<Vec<u8>>>::default();
-        let width = 1 + rand::random::<u32>() % 512;
-        let height = 1 + rand::random::<u32>() % 512;
-        let depth = 1 + rand::random::<u32>() % 4;
-        img.extents.x = width;
-        img.extents.y = height;
-        img.extents.z = depth;
-        img.data.resize_with(img.extents.z as usize, || {
-            (0..width * height * 4)
-                .map(|_| rand::random::<u8>())
-                .collect::<Vec<u8>>()
-        });
+/// Counts of the kinds of blocks in compressed data, see `Context::compress_stats`.
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq, Hash)]
+pub struct CompressStats {
+    /// The total number of blocks.
+    pub blocks: usize,
+    /// The number of constant-color (void-extent) blocks.
+    pub constant_blocks: usize,
+    /// The number of blocks that store one component in a separate plane of weights.
+    pub dual_plane_blocks: usize,
+    /// The number of blocks that are malformed and decode to the error color.
+    pub error_blocks: usize,
+    partitions: [u32; 5],
+}
 
-        let mut ctx = super::Context::default();
-        let swz = super::Swizzle::rgba();
+impl CompressStats {
+    /// The number of blocks with each partition count, indexed by the partition count. Constant
+    /// and malformed blocks have no partitions and are counted at index 0. Few blocks with
+    /// multiple partitions suggest raising the partition limit won't improve quality.
+    pub fn partition_histogram(&self) -> [u32; 5] {
+        self.partitions
+    }
+}
 
-        let data = ctx.compress(&img, swz).unwrap();
+/// Compressed image data along with the metadata needed to decompress it. This is the same
+/// information that is stored in the header of a `.astc` file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CompressedImage {
+    /// The block size the data was compressed with.
+    pub block: BlockSize,
+    /// The dimensions of the image.
+    pub extents: Extents,
+    /// The compressed blocks.
+    pub data: Vec<u8>,
+}
 
-        let img2 = ctx.decompress(&data, img.extents, swz).unwrap();
+impl CompressedImage {
+    /// A hash of the block size, extents and compressed data, for use as a cache key. This uses
+    /// 64-bit FNV-1a, so the hash is the same on every platform and with every version of Rust,
+    /// and can be stored on disk. It is not a cryptographic hash.
+    pub fn content_hash(&self) -> u64 {
+        const OFFSET_BASIS: u64 = 0xCBF2_9CE4_8422_2325;
+        const PRIME: u64 = 0x0000_0100_0000_01B3;
 
-        assert_eq!(img.extents, img2.extents);
-        assert_eq!(img.data.len(), img2.data.len());
-        assert!(img
-            .data
+        self.block
+            .extents()
+            .to_le_bytes()
             .iter()
-            .zip(img2.data.iter())
-            .all(|(a, b)| a.len() == b.len()));
+            .chain(&self.extents.to_le_bytes())
+            .chain(&self.data)
+            .fold(OFFSET_BASIS, |hash, &byte| {
+                (hash ^ byte as u64).wrapping_mul(PRIME)
+            })
+    }
 
-        // ASTC being a lossy compression algorithm, we can't compare the data between the two
-        // images, but we make sure that the two images are somewhat close.
-        assert!(
-            img.data
-                .iter()
+    /// Split the data into chunks of up to `rows_per_chunk` rows of blocks, for uploading large
+    /// textures in several copies (for example with `vkCmdCopyBufferToImage`). Each chunk is
+    /// yielded with the texel offset of its first block, and covers the full width of the image.
+    /// Chunks never span more than one layer of blocks, so each one is a single box of the
+    /// image. A `rows_per_chunk` of 0 is treated as 1.
+    ///
+    /// Returns `Error::BadParam` if the data isn't the size of the image compressed with its
+    /// block size, see `compressed_size`.
+    pub fn upload_chunks(
+        &self,
+        rows_per_chunk: u32,
+    ) -> Result<impl Iterator<Item = (Extents, &[u8])> + '_, Error> {
+        let block = self.block;
+        if self.data.len() != compressed_size(block, self.extents) {
+            return Err(Error::BadParam);
+        }
+
+        let grid = block.grid(self.extents);
+        let block = block.extents();
+        let row_len = grid.x as usize * Extents::block_byte_cost();
+        let rows_per_chunk = rows_per_chunk.max(1);
+
+        Ok((0..grid.z).flat_map(move |z| {
+            (0..grid.y).step_by(rows_per_chunk as usize).map(move |y| {
+                let rows = rows_per_chunk.min(grid.y - y) as usize;
+                let start = (z * grid.y + y) as usize * row_len;
+
+                (
+                    Extents::new_3d(0, y * block.y, z * block.z),
+                    &self.data[start..start + rows * row_len],
+                )
+            })
+        }))
+    }
+}
+
+/// The compressed levels of a mip chain, created with `Context::compress_mips`. Level 0 is the
+/// full size image, and each following level is half the size of the previous one in every
+/// dimension (rounded down, to a minimum of 1), see `mip_extents`.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct MipChain {
+    /// The compressed data of each level, starting with the largest.
+    pub levels: Vec<Vec<u8>>,
+}
+
+/// An individual component of a swizzle.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum Selector {
+    /// Select the red component
+    Red,
+    /// Select the green component
+    Green,
+    /// Select the blue component
+    Blue,
+    /// Select the alpha component
+    Alpha,
+    /// Select the z component of a normal, which is reconstructed from the x and y components
+    /// stored in the red and alpha components. Only valid when decompressing.
+    Z,
+    /// Constant 1.
+    One,
+    /// Constant 0.
+    Zero,
+}
+
+impl Selector {
+    const ALL: [Self; 7] = [
+        Self::Red,
+        Self::Green,
+        Self::Blue,
+        Self::Alpha,
+        Self::Zero,
+        Self::One,
+        Self::Z,
+    ];
+
+    fn into_sys(self) -> astcenc_sys::astcenc_swz {
+        match self {
+            Self::Red => astcenc_sys::astcenc_swz_ASTCENC_SWZ_R,
+            Self::Green => astcenc_sys::astcenc_swz_ASTCENC_SWZ_G,
+            Self::Blue => astcenc_sys::astcenc_swz_ASTCENC_SWZ_B,
+            Self::Alpha => astcenc_sys::astcenc_swz_ASTCENC_SWZ_A,
+            Self::Z => astcenc_sys::astcenc_swz_ASTCENC_SWZ_Z,
+            Self::One => astcenc_sys::astcenc_swz_ASTCENC_SWZ_1,
+            Self::Zero => astcenc_sys::astcenc_swz_ASTCENC_SWZ_0,
+        }
+    }
+}
+
+/// A component selection swizzle. The image must always be in RGBA order, even if the G, B
+/// and/or A components are never used.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Swizzle {
+    /// The component to use for the red channel.
+    pub r: Selector,
+    /// The component to use for the green channel.
+    pub g: Selector,
+    /// The component to use for the blue channel.
+    pub b: Selector,
+    /// The component to use for the alpha channel.
+    pub a: Selector,
+}
+
+impl Swizzle {
+    /// Default swizzle for greyscale without alpha.
+    ///
+    /// To access the output in a shader, use the `.g` swizzle.
+    pub fn rrr1() -> Self {
+        Self {
+            r: Selector::Red,
+            g: Selector::Red,
+            b: Selector::Red,
+            a: Selector::One,
+        }
+    }
+
+    /// Default swizzle for greyscale with alpha.
+    ///
+    /// To access the output in a shader, use the `.ga` swizzle.
+    pub fn rrrg() -> Self {
+        Self {
+            r: Selector::Red,
+            g: Selector::Red,
+            b: Selector::Red,
+            a: Selector::Green,
+        }
+    }
+
+    /// Default swizzle for RGB without alpha.
+    ///
+    /// To access the output in a shader, use the `.rga` swizzle.
+    pub fn rgb1() -> Self {
+        Self {
+            r: Selector::Red,
+            g: Selector::Green,
+            b: Selector::Blue,
+            a: Selector::One,
+        }
+    }
+
+    /// Default swizzle for RGB with alpha.
+    ///
+    /// To access the output in a shader, use the `.rga` swizzle.
+    pub fn rgba() -> Self {
+        Self {
+            r: Selector::Red,
+            g: Selector::Green,
+            b: Selector::Blue,
+            a: Selector::Alpha,
+        }
+    }
+
+    /// Decompression swizzle for normal maps that were compressed with `rrrg`. Returns X and Y
+    /// in the red and green components and reconstructs Z in the blue component.
+    pub fn raz1() -> Self {
+        Self {
+            r: Selector::Red,
+            g: Selector::Alpha,
+            b: Selector::Z,
+            a: Selector::One,
+        }
+    }
+
+    /// The components of a source texel as they are stored by compressing with this swizzle.
+    fn apply<D: DataType + Copy>(self, texel: &[D]) -> [f32; 4] {
+        [self.r, self.g, self.b, self.a].map(|selector| match selector {
+            Selector::Red => texel[0].to_f32(),
+            Selector::Green => texel[1].to_f32(),
+            Selector::Blue => texel[2].to_f32(),
+            Selector::Alpha => texel[3].to_f32(),
+            Selector::One => 1.0,
+            // `Selector::Z` is rejected by the compressor.
+            Selector::Zero | Selector::Z => 0.0,
+        })
+    }
+
+    fn into_sys(self) -> astcenc_sys::astcenc_swizzle {
+        astcenc_sys::astcenc_swizzle {
+            r: self.r.into_sys(),
+            g: self.g.into_sys(),
+            b: self.b.into_sys(),
+            a: self.a.into_sys(),
+        }
+    }
+}
+
+/// How `Context::compress_edged` fills the texels of edge blocks that are outside the image.
+/// These texels are never visible when sampling the image itself, but they affect how the texels
+/// inside the image are encoded, and they are visible when the data is sampled as a larger
+/// texture, such as when using the padding for filtering.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum EdgeMode {
+    /// Repeat the nearest texel on the edge of the image. This is what `compress` does.
+    Clamp,
+    /// Continue with the texels from the opposite edge of the image, for tiling textures.
+    Wrap,
+    /// Fill with transparent black, for UI content.
+    Transparent,
+}
+
+/// How `Context::compress_with_overrides` encodes a particular block instead of searching for the
+/// best encoding.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum BlockOverride {
+    /// Encode the block as a single constant color, the average of its texels. For the LDR
+    /// profiles the color is clamped to the LDR range, and for the HDR profiles it is stored as
+    /// FP16.
+    Constant,
+    /// Encode the block with the given number of partitions. The encoder has no way to restrict
+    /// the search for a single block, so this is currently treated as a request that can't be
+    /// honored, and the block keeps the encoding the encoder picked for it.
+    Partitions(u32),
+}
+
+/// The order the compressed blocks of an image are stored in, see `reorder_blocks`.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum BlockOrder {
+    /// Blocks are stored in x-major, then y, then z order. This is the order used by `compress`
+    /// and `decompress`, and the one expected by graphics APIs.
+    RowMajor,
+    /// Blocks are stored in Morton (Z-order curve) order, which keeps blocks that are close
+    /// together in the image close together in memory. For grids that aren't a power of two in
+    /// size, positions outside the grid are skipped.
+    Morton,
+}
+
+impl BlockOrder {
+    /// The row-major index of the block stored at each position in this order.
+    fn permutation(self, grid: Extents) -> Vec<usize> {
+        let mut indices = (0..grid.x * grid.y * grid.z).collect::<Vec<u32>>();
+
+        if self == Self::Morton {
+            // Spread the bits of `value` out so there are two zero bits between each of them.
+            let spread = |value: u32| {
+                (0..21).fold(0u64, |code, bit| {
+                    code | (((value as u64) >> bit) & 1) << (bit * 3)
+                })
+            };
+
+            indices.sort_by_key(|&index| {
+                let x = index % grid.x;
+                let y = index / grid.x % grid.y;
+                let z = index / (grid.x * grid.y);
+                spread(x) | spread(y) << 1 | spread(z) << 2
+            });
+        }
+
+        indices.into_iter().map(|index| index as usize).collect()
+    }
+}
+
+/// A compact description of how a texture was compressed and how it should be decoded, for
+/// storing alongside the compressed data in asset databases. Convert it to and from a single
+/// integer with `FormatTag::to_u32` and `FormatTag::from_u32`, which use the following layout:
+///
+/// | Bits    | Contents                                                                  |
+/// |---------|---------------------------------------------------------------------------|
+/// | 0..4    | Block width                                                               |
+/// | 4..8    | Block height                                                              |
+/// | 8..12   | Block depth                                                               |
+/// | 12..14  | Profile: 0 = `LdrRgba`, 1 = `LdrSrgb`, 2 = `HdrRgbLdrA`, 3 = `HdrRgba`    |
+/// | 14..26  | Swizzle, 3 bits each for `r`, `g`, `b` and `a` in that order              |
+/// | 26..32  | Reserved, always 0                                                        |
+///
+/// Each swizzle selector is stored as 0 = `Red`, 1 = `Green`, 2 = `Blue`, 3 = `Alpha`,
+/// 4 = `Zero`, 5 = `One`, 6 = `Z`.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct FormatTag {
+    /// The block size the data was compressed with.
+    pub block: BlockSize,
+    /// The profile the data was compressed with.
+    pub profile: Profile,
+    /// The swizzle to decompress or sample the data with.
+    pub swizzle: Swizzle,
+}
+
+impl FormatTag {
+    const PROFILES: [Profile; 4] = [
+        Profile::LdrRgba,
+        Profile::LdrSrgb,
+        Profile::HdrRgbLdrA,
+        Profile::HdrRgba,
+    ];
+
+    /// Pack the tag into an integer. Every dimension of a valid block size fits in 4 bits, so
+    /// this can't fail.
+    pub fn to_u32(&self) -> u32 {
+        let Extents { x, y, z } = self.block.extents();
+
+        let profile = Self::PROFILES
+            .iter()
+            .position(|&profile| profile == self.profile)
+            .unwrap() as u32;
+        let selector = |selector: Selector| {
+            Selector::ALL
+                .iter()
+                .position(|&other| other == selector)
+                .unwrap() as u32
+        };
+
+        x | y << 4
+            | z << 8
+            | profile << 12
+            | selector(self.swizzle.r) << 14
+            | selector(self.swizzle.g) << 17
+            | selector(self.swizzle.b) << 20
+            | selector(self.swizzle.a) << 23
+    }
+
+    /// Unpack a tag created with `FormatTag::to_u32`. Returns `Error::BadData` if the reserved
+    /// bits are set or any of the fields is invalid, including a block size ASTC doesn't support.
+    pub fn from_u32(tag: u32) -> Result<Self, Error> {
+        if tag >> 26 != 0 {
+            return Err(Error::BadData);
+        }
+
+        let block = Extents::new_3d(tag & 0xF, tag >> 4 & 0xF, tag >> 8 & 0xF);
+        let block = BlockSize::try_from(block).map_err(|_| Error::BadData)?;
+
+        let selector = |shift: u32| {
+            Selector::ALL
+                .get((tag >> shift & 0x7) as usize)
+                .copied()
+                .ok_or(Error::BadData)
+        };
+
+        Ok(Self {
+            block,
+            profile: Self::PROFILES[(tag >> 12 & 0x3) as usize],
+            swizzle: Swizzle {
+                r: selector(14)?,
+                g: selector(17)?,
+                b: selector(20)?,
+                a: selector(23)?,
+            },
+        })
+    }
+}
+
+/// The original values of the texels kept by `Context::compress_with_exact`, with their
+/// coordinates.
+type ExactTexels<D> = Vec<(Extents, [D; 4])>;
+
+/// The compressed data and the 8-bit preview returned by `Context::compress_with_preview`.
+type WithPreview = (Vec<u8>, Image<Vec<Vec<u8>>>);
+
+impl Context {
+    /// Create a new context from the given config (see `ConfigBuilder` for more information on this
+    /// config). Returns an error in the case that the config is invalid or the context could not be
+    /// allocated.
+    pub fn new(config: Config) -> Result<Self, Error> {
+        Ok(Self {
+            inner: Self::alloc(&config)?,
+            config,
+            cache: CompressCache::default(),
+        })
+    }
+
+    // TODO: Do this properly somehow
+    const THREADS: usize = 1;
+
+    fn alloc(config: &Config) -> Result<NonNull<astcenc_sys::astcenc_context>, Error> {
+        let mut cfg: MaybeUninit<*mut astcenc_sys::astcenc_context> = MaybeUninit::uninit();
+
+        error_code_to_result(unsafe {
+            astcenc_sys::astcenc_context_alloc(&config.inner, Self::THREADS as _, cfg.as_mut_ptr())
+        })?;
+
+        unsafe { NonNull::new(cfg.assume_init()).ok_or(Error::Unknown) }
+    }
+
+    /// Replace the underlying context with a newly-allocated one using the same config, for
+    /// recovering from a context that has been left in a bad state.
+    fn reinit(&mut self) -> Result<(), Error> {
+        let inner = Self::alloc(&self.config)?;
+        unsafe { astcenc_sys::astcenc_context_free(self.inner.as_ptr()) };
+        self.inner = inner;
+        Ok(())
+    }
+
+    /// A human-readable summary of the settings of this context, for logging alongside errors so
+    /// that failures can be reproduced, for example
+    /// `profile LdrRgba, block size 4x4x1, preset 60, flags USE_ALPHA_WEIGHT, 1 thread(s)`.
+    pub fn describe(&self) -> String {
+        let builder = &self.config.builder;
+        let block = self.config.block_size();
+
+        let flags = builder
+            .flags
+            .iter_names()
+            .map(|(name, _)| name)
+            .collect::<Vec<_>>();
+
+        format!(
+            "profile {:?}, block size {}x{}x{}, preset {}, flags {}, {} thread(s)",
+            builder.profile,
+            block.x,
+            block.y,
+            block.z,
+            builder.preset.0,
+            if flags.is_empty() {
+                "none".to_string()
+            } else {
+                flags.join(" | ")
+            },
+            Self::THREADS,
+        )
+    }
+
+    /// Whether compression with this context actually uses a perceptual error metric. The
+    /// encoder only has a perceptual metric for normal maps, so `Flags::USE_PERCEPTUAL` is
+    /// silently ignored unless `Flags::MAP_NORMAL` is set as well.
+    pub fn perceptual_active(&self) -> bool {
+        self.config
+            .builder
+            .flags
+            .contains(Flags::USE_PERCEPTUAL | Flags::MAP_NORMAL)
+    }
+
+    /// Turn `Flags::USE_PERCEPTUAL` on or off for later compressions, keeping the rest of the
+    /// config, for pipelines that compress normal maps with the perceptual metric and other
+    /// textures without it. See `Context::perceptual_active` for when the flag has an effect.
+    ///
+    /// `astcenc` fixes the config when a context is allocated, so changing the flag reallocates
+    /// the underlying context, which costs about as much as `Context::new`, and clears the cache
+    /// of `Context::compress_cached`. Setting the flag to its current state does nothing, so
+    /// grouping textures by setting avoids most of the cost. If the new context can't be
+    /// allocated the error is returned and this context is left unchanged.
+    pub fn set_perceptual(&mut self, perceptual: bool) -> Result<(), Error> {
+        let mut builder = self.config.builder.clone();
+        if builder.flags.contains(Flags::USE_PERCEPTUAL) == perceptual {
+            return Ok(());
+        }
+
+        builder.flags.set(Flags::USE_PERCEPTUAL, perceptual);
+        let config = builder.build()?;
+        let inner = Self::alloc(&config)?;
+
+        unsafe { astcenc_sys::astcenc_context_free(self.inner.as_ptr()) };
+        self.inner = inner;
+        self.config = config;
+        self.cache = CompressCache::default();
+
+        Ok(())
+    }
+
+    /// The block size this context compresses and decompresses with.
+    pub fn block_size(&self) -> BlockSize {
+        BlockSize(self.config.block_size())
+    }
+
+    /// Whether `data` has the length of an image with the given extents compressed with the block
+    /// size of this context. Loaders can check this before calling `Context::decompress`, since
+    /// data compressed with a different block size otherwise decodes to garbage. Data compressed
+    /// with a different profile can't be detected this way, see `guess_profile`.
+    pub fn is_compatible_with(&self, data: &[u8], extents: Extents) -> bool {
+        data.len() == compressed_size(self.block_size(), extents)
+    }
+
+    fn check_data_len(&self, data: &[u8], extents: Extents) -> Result<(), Error> {
+        if !self.is_compatible_with(data, extents) {
+            return Err(Error::BadBlockSize);
+        }
+
+        Ok(())
+    }
+
+    /// Create a new context with the default config, returning an error if it could not be
+    /// allocated.
+    pub fn try_default() -> Result<Self, Error> {
+        Self::new(ConfigBuilder::default().build()?)
+    }
+
+    /// Compress a single throwaway block, so that any work the encoder does lazily on its first
+    /// use is done now instead of during the first real compression. Useful for services that
+    /// need predictable latency. Does nothing for contexts created with `Flags::DECOMPRESS_ONLY`.
+    pub fn prewarm(&mut self) -> Result<(), Error> {
+        if self.config.builder.flags.contains(Flags::DECOMPRESS_ONLY) {
+            return Ok(());
+        }
+
+        let extents = self.config.block_size();
+        let image = Image {
+            extents,
+            data: vec![vec![0.5f32; (extents.x * extents.y * 4) as usize]; extents.z as usize],
+        };
+
+        self.compress(&image, Swizzle::rgba()).map(|_| ())
+    }
+
+    /// Compress the given image, returning a byte vector that can be sent to the GPU. Returns
+    /// `Error::BadParam` if the component type of the image can't be used with the profile of
+    /// this context, see `supported_types`, and `Error::Unknown` if the encoder doesn't write
+    /// every block of the output.
+    ///
+    /// Compression is deterministic: the encoder's search has no random component, so the same
+    /// image, swizzle and config always produce identical output, which makes builds reproducible.
+    pub fn compress<D, T, L>(
+        &mut self,
+        image: &Image<T>,
+        swizzle: Swizzle,
+    ) -> Result<Vec<u8>, Error>
+    where
+        D: DataType,
+        T: Deref<Target = [L]>,
+        L: Deref<Target = [D]>,
+    {
+        let bytes = compressed_size(self.block_size(), image.extents);
+        let mut out = Vec::with_capacity(bytes);
+
+        self.compress_uninit(image, swizzle, &mut out.spare_capacity_mut()[..bytes])?;
+
+        unsafe { out.set_len(bytes) };
+
+        Ok(out)
+    }
+
+    /// Compress the given image into a file at `path`, which is created (or truncated) with the
+    /// size of the compressed data and memory-mapped, so the encoder writes straight into the
+    /// file instead of into an intermediate buffer. This is useful for very large outputs such as
+    /// big 3D volumes. The file holds only the compressed blocks; use
+    /// `Context::compress_to_astc_file` for a file with a header. Enabled with the `memmap2`
+    /// feature.
+    #[cfg(feature = "memmap2")]
+    pub fn compress_to_mmap<D, T, L, P>(
+        &mut self,
+        image: &Image<T>,
+        swizzle: Swizzle,
+        path: P,
+    ) -> Result<(), Error>
+    where
+        D: DataType,
+        T: Deref<Target = [L]>,
+        L: Deref<Target = [D]>,
+        P: AsRef<std::path::Path>,
+    {
+        image.check_len()?;
+
+        let bytes = compressed_size(self.block_size(), image.extents);
+        let file = std::fs::OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(path)?;
+        file.set_len(bytes as u64)?;
+
+        // Empty files can't be mapped.
+        if bytes == 0 {
+            return Ok(());
+        }
+
+        let mut map = unsafe { memmap2::MmapMut::map_mut(&file)? };
+        // Viewing initialized bytes as `MaybeUninit` is always sound.
+        let out = unsafe { &mut *(&mut map[..] as *mut [u8] as *mut [MaybeUninit<u8>]) };
+        self.compress_uninit(image, swizzle, out)?;
+
+        map.flush()?;
+
+        Ok(())
+    }
+
+    /// Compress the given image into `out`, which must be exactly the size of the compressed
+    /// image. Every byte of `out` is initialized when this returns successfully. Returns
+    /// `Error::Unknown` if the encoder leaves any block of `out` unwritten.
+    fn compress_uninit<D, T, L>(
+        &mut self,
+        image: &Image<T>,
+        swizzle: Swizzle,
+        out: &mut [MaybeUninit<u8>],
+    ) -> Result<(), Error>
+    where
+        D: DataType,
+        T: Deref<Target = [L]>,
+        L: Deref<Target = [D]>,
+    {
+        image.check_len()?;
+
+        if !supported_types(self.config.builder.profile).contains(&D::TYPE) {
+            return Err(Error::BadParam);
+        }
+
+        if out.len() != compressed_size(self.block_size(), image.extents) {
+            return Err(Error::BadParam);
+        }
+
+        // An all-zero block is a reserved encoding that the encoder never produces, so starting
+        // from zeroes shows afterwards whether every block was written. Streaming formats rely on
+        // there being exactly one encoded block for each block of the image.
+        out.fill(MaybeUninit::new(0));
+
+        let mut image_data_pointers = image
+            .data
+            .iter()
+            .map(|layer| layer.as_ptr() as *const c_void)
+            .collect::<Vec<_>>();
+        let mut image_sys = astcenc_sys::astcenc_image {
+            dim_x: image.extents.x,
+            dim_y: image.extents.y,
+            dim_z: image.extents.z,
+            data_type: D::TYPE.into_sys(),
+            data: image_data_pointers.as_mut_ptr() as *mut *mut _,
+        };
+
+        error_code_to_result(unsafe {
+            astcenc_sys::astcenc_compress_image(
+                self.inner.as_mut(),
+                &mut image_sys as *mut _,
+                &swizzle.into_sys(),
+                out.as_mut_ptr() as *mut u8,
+                out.len(),
+                0,
+            )
+        })?;
+
+        self.reset()?;
+
+        let unwritten = out
+            .chunks_exact(Extents::block_byte_cost())
+            .any(|block| block.iter().all(|byte| unsafe { byte.assume_init() } == 0));
+        if unwritten {
+            return Err(Error::Unknown);
+        }
+
+        Ok(())
+    }
+
+    /// Compress the given image like `compress`, but keep the result in a cache on this context,
+    /// and return the cached data without encoding again when the same image is compressed with
+    /// the same swizzle later, for example in incremental builds. The cache is keyed by a SHA-256
+    /// digest of the extents, component type, swizzle and data, so no copy of the image is kept.
+    /// It holds up to 32 results and 64 MiB of compressed data, evicting the least recently used
+    /// results when full, and results larger than that aren't cached. The cache is cleared when
+    /// the config of the context changes, see `Context::set_perceptual`.
+    pub fn compress_cached<D, T, L>(
+        &mut self,
+        image: &Image<T>,
+        swizzle: Swizzle,
+    ) -> Result<Vec<u8>, Error>
+    where
+        D: DataType,
+        T: Deref<Target = [L]>,
+        L: Deref<Target = [D]>,
+    {
+        let key = CacheKey::new(image, swizzle);
+
+        if let Some(data) = self.cache.get(&key) {
+            return Ok(data);
+        }
+
+        let data = self.compress(image, swizzle)?;
+        self.cache.insert(key, data.clone());
+
+        Ok(data)
+    }
+
+    /// Compress a 2D image like `compress`, and also return an 8-bit RGBA preview at 1/8 of its
+    /// width and height (rounded up), for loading UIs that show a blurry image while the full
+    /// texture streams in. Each preview texel is the average of an 8x8 box of source texels, or
+    /// of the part of it inside the image at the right and bottom edges. The preview is made from
+    /// the source image rather than the decoded blocks, so nothing is decompressed, and the
+    /// swizzle isn't applied to it. HDR components are clamped to the LDR range.
+    ///
+    /// Returns `Error::BadParam` for 3D images.
+    pub fn compress_with_preview<D, T, L>(
+        &mut self,
+        image: &Image<T>,
+        swizzle: Swizzle,
+    ) -> Result<WithPreview, Error>
+    where
+        D: DataType + Copy,
+        T: Deref<Target = [L]>,
+        L: Deref<Target = [D]>,
+    {
+        const SCALE: u32 = 8;
+
+        image.check_len()?;
+        if image.extents.z != 1 {
+            return Err(Error::BadParam);
+        }
+
+        let src = image.extents;
+        let extents = Extents::new((src.x + SCALE - 1) / SCALE, (src.y + SCALE - 1) / SCALE);
+        let layer = &image.data[0];
+
+        let mut preview = Vec::with_capacity((extents.x * extents.y * 4) as usize);
+        for y in 0..extents.y {
+            for x in 0..extents.x {
+                let mut sum = [0.0f32; 4];
+                let (x_end, y_end) = (((x + 1) * SCALE).min(src.x), ((y + 1) * SCALE).min(src.y));
+                for sy in y * SCALE..y_end {
+                    for sx in x * SCALE..x_end {
+                        let start = ((sy * src.x + sx) * 4) as usize;
+                        for (sum, &component) in sum.iter_mut().zip(&layer[start..start + 4]) {
+                            *sum += component.to_f32();
+                        }
+                    }
+                }
+
+                let texels = ((x_end - x * SCALE) * (y_end - y * SCALE)) as f32;
+                preview.extend(sum.iter().map(|&sum| u8::from_f32(sum / texels)));
+            }
+        }
+
+        let data = self.compress(image, swizzle)?;
+
+        Ok((
+            data,
+            Image {
+                extents,
+                data: vec![preview],
+            },
+        ))
+    }
+
+    /// Compress pixels of any type by converting each of them to RGBA with `convert`, for example
+    /// from a custom pixel struct. `pixels` holds the pixels of each layer one after another.
+    /// The converted image is staged in a temporary buffer before being compressed. Returns
+    /// `Error::BadParam` if `pixels` doesn't hold exactly one pixel per texel of `extents`.
+    pub fn compress_mapped<P, D, F>(
+        &mut self,
+        pixels: &[P],
+        extents: Extents,
+        convert: F,
+        swizzle: Swizzle,
+    ) -> Result<Vec<u8>, Error>
+    where
+        D: DataType + Copy,
+        F: Fn(&P) -> [D; 4],
+    {
+        let layer_len = extents.x as usize * extents.y as usize;
+        if pixels.len() != layer_len * extents.z as usize {
+            return Err(Error::BadParam);
+        }
+
+        let data = if layer_len == 0 {
+            vec![Vec::new(); extents.z as usize]
+        } else {
+            pixels
+                .chunks_exact(layer_len)
+                .map(|layer| layer.iter().flat_map(&convert).collect::<Vec<D>>())
+                .collect::<Vec<_>>()
+        };
+
+        self.compress(&Image { extents, data }, swizzle)
+    }
+
+    /// Compress a box out of a larger image stored in a single buffer, without the caller having to
+    /// copy it into an `Image` first. Rows of `full` start every `row_stride` components (which
+    /// allows for padding at the end of each row) and layers start every `row_stride *
+    /// full_extents.y` components. The box is copied into a tightly packed staging buffer before
+    /// compressing. Returns `Error::BadParam` if the box doesn't fit in `full_extents`, if
+    /// `row_stride` is smaller than a row, or if `full` is too short for the given layout.
+    pub fn compress_subregion<D>(
+        &mut self,
+        full: &[D],
+        full_extents: Extents,
+        region_origin: Extents,
+        region_size: Extents,
+        row_stride: usize,
+        swizzle: Swizzle,
+    ) -> Result<Vec<u8>, Error>
+    where
+        D: DataType + Copy,
+    {
+        let layer_stride = row_stride * full_extents.y as usize;
+        if region_origin.x + region_size.x > full_extents.x
+            || region_origin.y + region_size.y > full_extents.y
+            || region_origin.z + region_size.z > full_extents.z
+            || row_stride < full_extents.x as usize * 4
+            || full.len() < layer_stride * full_extents.z as usize
+        {
+            return Err(Error::BadParam);
+        }
+
+        let data = (region_origin.z..region_origin.z + region_size.z)
+            .map(|z| {
+                (region_origin.y..region_origin.y + region_size.y)
+                    .flat_map(|y| {
+                        let start = z as usize * layer_stride
+                            + y as usize * row_stride
+                            + region_origin.x as usize * 4;
+                        full[start..start + region_size.x as usize * 4]
+                            .iter()
+                            .copied()
+                    })
+                    .collect::<Vec<D>>()
+            })
+            .collect::<Vec<_>>();
+
+        self.compress(
+            &Image {
+                extents: region_size,
+                data,
+            },
+            swizzle,
+        )
+    }
+
+    /// Compress the given image like `compress`, but choose how the texels of partial blocks at
+    /// the right, bottom and back edges of the image are filled in, see `EdgeMode`. The image is
+    /// copied into a staging buffer padded to a whole number of blocks, and the result is the
+    /// same size as, and decompresses like, the output of `compress` for the same extents.
+    pub fn compress_edged<D, T, L>(
+        &mut self,
+        image: &Image<T>,
+        swizzle: Swizzle,
+        mode: EdgeMode,
+    ) -> Result<Vec<u8>, Error>
+    where
+        D: DataType + Copy,
+        T: Deref<Target = [L]>,
+        L: Deref<Target = [D]>,
+    {
+        image.check_len()?;
+
+        let block = self.block_size();
+        let src = image.extents;
+        if image_from_block_grid(block.grid(src), block) == src
+            || [src.x, src.y, src.z].contains(&0)
+        {
+            return self.compress(image, swizzle);
+        }
+
+        self.compress(&image.pad_to_block(block, mode)?, swizzle)
+    }
+
+    /// Compress the given image with the identity swizzle, storing the components in the order
+    /// they are in the image. This is the same as `compress` with `Swizzle::rgba()`.
+    pub fn compress_identity<D, T, L>(&mut self, image: &Image<T>) -> Result<Vec<u8>, Error>
+    where
+        D: DataType,
+        T: Deref<Target = [L]>,
+        L: Deref<Target = [D]>,
+    {
+        self.compress(image, Swizzle::rgba())
+    }
+
+    /// Compress the given image like `compress`, but re-encode the blocks covering each region
+    /// with that region's preset. Regions are given as `(origin, size, preset)` in texels and are
+    /// expanded outwards to whole blocks; later regions take priority over earlier ones where they
+    /// overlap. Every region requires a separate pass with its own context, so this is
+    /// considerably slower than a single `compress` call.
+    pub fn compress_regions<D, T, L>(
+        &mut self,
+        image: &Image<T>,
+        swizzle: Swizzle,
+        regions: &[(Extents, Extents, Preset)],
+    ) -> Result<Vec<u8>, Error>
+    where
+        D: DataType + Copy,
+        T: Deref<Target = [L]>,
+        L: Deref<Target = [D]>,
+    {
+        let mut out = self.compress(image, swizzle)?;
+
+        let block = self.config.block_size();
+        let grid = image.extents.in_blocks(block);
+
+        for &(origin, size, preset) in regions {
+            if origin.x + size.x > image.extents.x
+                || origin.y + size.y > image.extents.y
+                || origin.z + size.z > image.extents.z
+            {
+                return Err(Error::BadParam);
+            }
+
+            if size.x == 0 || size.y == 0 || size.z == 0 {
+                continue;
+            }
+
+            let first = Extents::new_3d(origin.x / block.x, origin.y / block.y, origin.z / block.z);
+            let last = Extents::new_3d(origin.x + size.x, origin.y + size.y, origin.z + size.z)
+                .in_blocks(block);
+            let start = Extents::new_3d(first.x * block.x, first.y * block.y, first.z * block.z);
+            let end = Extents::new_3d(
+                (last.x * block.x).min(image.extents.x),
+                (last.y * block.y).min(image.extents.y),
+                (last.z * block.z).min(image.extents.z),
+            );
+
+            let region = image.region(
+                start,
+                Extents::new_3d(end.x - start.x, end.y - start.y, end.z - start.z),
+            );
+
+            let mut ctx = Context::new(self.config.builder.clone().with_preset(preset).build()?)?;
+            let region_data = ctx.compress(&region, swizzle)?;
+
+            copy_blocks(
+                &mut out,
+                grid,
+                &region_data,
+                region.extents.in_blocks(block),
+                first,
+            );
+        }
+
+        Ok(out)
+    }
+
+    /// Compress several images into a single atlas with the given extents, each with its own
+    /// swizzle, for example to pack normal maps and albedo textures into one texture. `layout`
+    /// holds the origin of each image in the atlas, and must be the same length as `images`.
+    ///
+    /// Every origin must be a multiple of the block size, and every image must be a multiple of
+    /// the block size unless it reaches the edge of the atlas, so that no block contains texels
+    /// from more than one image. Images that overlap overwrite the earlier ones, and areas not
+    /// covered by any image are transparent black. Returns `Error::BadParam` if the layout
+    /// doesn't match these requirements or an image doesn't fit in the atlas.
+    pub fn compress_atlas<D, T, L>(
+        &mut self,
+        extents: Extents,
+        images: &[(Image<T>, Swizzle)],
+        layout: &[Extents],
+    ) -> Result<Vec<u8>, Error>
+    where
+        D: DataType,
+        T: Deref<Target = [L]>,
+        L: Deref<Target = [D]>,
+    {
+        if images.len() != layout.len() {
+            return Err(Error::BadParam);
+        }
+
+        let block = self.config.block_size();
+        let grid = extents.in_blocks(block);
+        let mut out = constant_block([0; 4]).repeat((grid.x * grid.y * grid.z) as usize);
+
+        for ((image, swizzle), &origin) in images.iter().zip(layout) {
+            let size = image.extents;
+            let fits = |origin: u32, size: u32, extent: u32, block: u32| {
+                origin % block == 0
+                    && origin + size <= extent
+                    && (size % block == 0 || origin + size == extent)
+            };
+
+            if !fits(origin.x, size.x, extents.x, block.x)
+                || !fits(origin.y, size.y, extents.y, block.y)
+                || !fits(origin.z, size.z, extents.z, block.z)
+            {
+                return Err(Error::BadParam);
+            }
+
+            let data = self.compress(image, *swizzle)?;
+
+            copy_blocks(
+                &mut out,
+                grid,
+                &data,
+                size.in_blocks(block),
+                Extents::new_3d(origin.x / block.x, origin.y / block.y, origin.z / block.z),
+            );
+        }
+
+        Ok(out)
+    }
+
+    /// Check that `data` is a complete compressed image of the given extents and that every block
+    /// in it decodes without error. The decoded output is discarded. If `block` and `profile` are
+    /// those of this context it is used for decoding, and otherwise a temporary
+    /// decompression-only context is created for them, since a context can only decode the block
+    /// size and profile it was created with. Returns `Error::BadParam` if the data has the wrong
+    /// length and `Error::BadData` if any block is malformed.
+    pub fn validate(
+        &mut self,
+        data: &[u8],
+        block: BlockSize,
+        extents: Extents,
+        profile: Profile,
+    ) -> Result<(), Error> {
+        let mut temporary;
+        let ctx = if block == self.block_size() && profile == self.config.builder.profile {
+            self
+        } else {
+            temporary = Context::new(
+                ConfigBuilder::new()
+                    .with_profile(profile)
+                    .with_block_size(block)
+                    .with_flags(Flags::DECOMPRESS_ONLY)
+                    .build()?,
+            )?;
+            &mut temporary
+        };
+
+        if data.len() != compressed_size(block, extents) {
+            return Err(Error::BadParam);
+        }
+
+        for compressed_block in data.chunks_exact(Extents::block_byte_cost()) {
+            if ctx.block_info(compressed_block)?.is_error_block {
+                return Err(Error::BadData);
+            }
+        }
+
+        ctx.decompress::<half::f16>(data, extents, Swizzle::rgba())?;
+
+        Ok(())
+    }
+
+    /// Change the component layout of compressed data by decoding it with `from` and compressing
+    /// the result with `to`, for example to match a shader that expects different channels. The
+    /// data is decoded with the profile of this context and the given block size, and re-encoded
+    /// with the settings of this context. This compresses already-compressed data, so the errors
+    /// of both passes add up; prefer compressing from the source image when it's available.
+    pub fn reswizzle(
+        &mut self,
+        data: &[u8],
+        block: BlockSize,
+        extents: Extents,
+        from: Swizzle,
+        to: Swizzle,
+    ) -> Result<Vec<u8>, Error> {
+        let decoded = if block == self.block_size() {
+            self.decompress::<half::f16>(data, extents, from)?
+        } else {
+            Context::new(
+                self.config
+                    .builder
+                    .clone()
+                    .with_block_size(block)
+                    .with_flags(Flags::DECOMPRESS_ONLY)
+                    .build()?,
+            )?
+            .decompress(data, extents, from)?
+        };
+
+        self.compress(&decoded, to)
+    }
+
+    /// Decompress an image without choosing the component type up front, using a temporary
+    /// decompression-only context. The LDR profiles decode to `DecodedImage::U8` and the HDR
+    /// profiles to `DecodedImage::F16`, which is what the `UNORM`/`SRGB` and `SFLOAT` formats of a
+    /// container imply. Returns `Error::BadBlockSize` if the data has the wrong length.
+    pub fn decompress_auto(
+        data: &[u8],
+        block: BlockSize,
+        extents: Extents,
+        profile: Profile,
+        swizzle: Swizzle,
+    ) -> Result<DecodedImage, Error> {
+        let mut ctx = Context::new(
+            ConfigBuilder::new()
+                .with_profile(profile)
+                .with_block_size(block)
+                .with_flags(Flags::DECOMPRESS_ONLY)
+                .build()?,
+        )?;
+
+        if profile.requires_float_input() {
+            Ok(DecodedImage::F16(ctx.decompress(data, extents, swizzle)?))
+        } else {
+            Ok(DecodedImage::U8(ctx.decompress(data, extents, swizzle)?))
+        }
+    }
+
+    /// Compress an image and estimate the PSNR of the result (see `psnr`) for quick feedback in
+    /// interactive tools. Only an evenly-spaced subset of at most 64 blocks is decoded for the
+    /// estimate, so it costs far less than decoding the whole image but can miss localized
+    /// artifacts. The source is compared with the data as stored, i.e. after applying `swizzle`.
+    pub fn compress_preview<D, T, L>(
+        &mut self,
+        image: &Image<T>,
+        swizzle: Swizzle,
+    ) -> Result<(Vec<u8>, f64), Error>
+    where
+        D: DataType + Copy,
+        T: Deref<Target = [L]>,
+        L: Deref<Target = [D]>,
+    {
+        const SAMPLES: usize = 64;
+
+        let data = self.compress(image, swizzle)?;
+
+        let block = self.config.block_size();
+        let width = image.extents.x as usize;
+        let block_count = data.len() / Extents::block_byte_cost();
+        let step = ((block_count + SAMPLES - 1) / SAMPLES).max(1);
+
+        let mut squared_error = 0.0;
+        let mut components = 0;
+        for index in (0..block_count).step_by(step) {
+            let start = index * Extents::block_byte_cost();
+            let decoded = self.decompress::<D>(
+                &data[start..start + Extents::block_byte_cost()],
+                block,
+                Swizzle::rgba(),
+            )?;
+
+            let (origin, size) = block_bounds(index, block, image.extents);
+
+            for z in 0..size.z {
+                let source = &image.data[(origin.z + z) as usize];
+                let decoded = &decoded.data[z as usize];
+
+                for y in 0..size.y {
+                    for x in 0..size.x {
+                        let src = ((origin.y + y) as usize * width + (origin.x + x) as usize) * 4;
+                        let dst = (y * block.x + x) as usize * 4;
+                        let expected = swizzle.apply(&source[src..src + 4]);
+
+                        for (expected, actual) in expected.iter().zip(&decoded[dst..dst + 4]) {
+                            squared_error += (*expected as f64 - actual.to_f32() as f64).powi(2);
+                            components += 1;
+                        }
+                    }
+                }
+            }
+        }
+
+        let estimate = if components == 0 {
+            f64::INFINITY
+        } else {
+            psnr_from_mse(squared_error / components as f64)
+        };
+
+        Ok((data, estimate))
+    }
+
+    /// Generate the next mip level from a compressed level by decoding it, halving it with a box
+    /// filter and compressing the result. Returns the compressed level and its extents, so
+    /// calls can be chained to build a full mip pyramid. Since the input is already lossy, prefer
+    /// downsampling from the source image where it is available.
+    ///
+    /// The previous level is decoded without a swizzle and the new level is compressed with
+    /// `swizzle`, so the stored components of the previous level pass through `swizzle` again.
+    /// Use `Swizzle::rgba()` to keep the stored component layout, or a swizzle that gives the
+    /// same result when applied twice, such as `Swizzle::rgb1()`.
+    ///
+    /// Returns `Error::BadBlockSize` if `block` isn't the block size of this context or the
+    /// data has the wrong length.
+    pub fn compress_next_mip<D>(
+        &mut self,
+        prev_level: &[u8],
+        prev_extents: Extents,
+        block: BlockSize,
+        swizzle: Swizzle,
+    ) -> Result<(Vec<u8>, Extents), Error>
+    where
+        D: DataType + Copy,
+    {
+        if block.extents() != self.config.block_size() {
+            return Err(Error::BadBlockSize);
+        }
+
+        let next = self
+            .decompress::<D>(prev_level, prev_extents, Swizzle::rgba())?
+            .downsampled();
+        let data = self.compress(&next, swizzle)?;
+
+        Ok((data, next.extents))
+    }
+
+    /// Generate a full mip chain from `base` and compress every level. Each level is downsampled
+    /// from the uncompressed previous level with a box filter, so compression errors don't
+    /// accumulate down the chain.
+    pub fn compress_mips<D, T, L>(
+        &mut self,
+        base: &Image<T>,
+        swizzle: Swizzle,
+    ) -> Result<MipChain, Error>
+    where
+        D: DataType + Copy,
+        T: Deref<Target = [L]>,
+        L: Deref<Target = [D]>,
+    {
+        let levels = self
+            .compress_mip_range(base, swizzle, 0..mip_level_count(base.extents))?
+            .into_iter()
+            .map(|(_, data)| data)
+            .collect();
+
+        Ok(MipChain { levels })
+    }
+
+    /// Like `Context::compress_mips`, but compresses the levels in parallel. The chain is
+    /// downsampled up front on the calling thread, then every level is compressed on the rayon
+    /// thread pool with a context per thread, created from the config of this context. The
+    /// output is the same as `Context::compress_mips`. Enabled with the `rayon` feature.
+    #[cfg(feature = "rayon")]
+    pub fn compress_mips_parallel<D, T, L>(
+        &self,
+        base: &Image<T>,
+        swizzle: Swizzle,
+    ) -> Result<MipChain, Error>
+    where
+        D: DataType + Copy + Sync,
+        T: Deref<Target = [L]> + Sync,
+        L: Deref<Target = [D]>,
+    {
+        use rayon::prelude::*;
+
+        base.check_len()?;
+
+        let level_count = mip_level_count(base.extents) as usize;
+        let mut downsampled: Vec<Image<Vec<Vec<D>>>> = Vec::with_capacity(level_count - 1);
+        for _ in 1..level_count {
+            let next = match downsampled.last() {
+                Some(image) => image.downsampled(),
+                None => base.downsampled(),
+            };
+            downsampled.push(next);
+        }
+
+        let levels = (0..level_count)
+            .into_par_iter()
+            .map_init(
+                || self.config.builder.clone().build().and_then(Context::new),
+                |context, level| {
+                    let context = context.as_mut().map_err(|error| *error)?;
+                    match level {
+                        0 => context.compress(base, swizzle),
+                        _ => context.compress(&downsampled[level - 1], swizzle),
+                    }
+                },
+            )
+            .collect::<Result<_, _>>()?;
+
+        Ok(MipChain { levels })
+    }
+
+    /// Like `Context::compress_mips`, but also return the PSNR of each level (see `psnr`), measured
+    /// between the downsampled level and its decompressed version. This shows where a chain loses
+    /// detail, for example from bad filtering or a block size that is too large for the small
+    /// levels. Every level is decompressed again to measure it, so this is slower than
+    /// `Context::compress_mips`.
+    pub fn compress_mips_verified<D, T, L>(
+        &mut self,
+        base: &Image<T>,
+        swizzle: Swizzle,
+    ) -> Result<Vec<(Vec<u8>, f64)>, Error>
+    where
+        D: DataType + Copy,
+        T: Deref<Target = [L]>,
+        L: Deref<Target = [D]>,
+    {
+        base.check_len()?;
+
+        let level_count = mip_level_count(base.extents);
+        let mut levels = Vec::with_capacity(level_count as usize);
+        let mut current: Option<Image<Vec<Vec<D>>>> = None;
+
+        for level in 0..level_count {
+            if level > 0 {
+                current = Some(match &current {
+                    Some(image) => image.downsampled(),
+                    None => base.downsampled(),
+                });
+            }
+
+            levels.push(match &current {
+                Some(image) => self.compress_verified(image, swizzle)?,
+                None => self.compress_verified(base, swizzle)?,
+            });
+        }
+
+        Ok(levels)
+    }
+
+    /// Compress an image and measure the PSNR of the result against the source as stored, i.e.
+    /// after applying `swizzle`.
+    fn compress_verified<D, T, L>(
+        &mut self,
+        image: &Image<T>,
+        swizzle: Swizzle,
+    ) -> Result<(Vec<u8>, f64), Error>
+    where
+        D: DataType + Copy,
+        T: Deref<Target = [L]>,
+        L: Deref<Target = [D]>,
+    {
+        let data = self.compress(image, swizzle)?;
+        let decoded = self.decompress::<D>(&data, image.extents, Swizzle::rgba())?;
+        let expected = image.map_texels(|texel| swizzle.apply(&texel).map(D::from_f32));
+
+        Ok((data, psnr(&expected, &decoded)?))
+    }
+
+    /// Compress only the levels in `range` of the mip chain generated from `base`, returning the
+    /// index and compressed data of each level. The image is downsampled to the first level in
+    /// the range without compressing the levels before it. Returns `Error::BadParam` if the range
+    /// extends past the last level of the chain, see `mip_level_count`.
+    pub fn compress_mip_range<D, T, L>(
+        &mut self,
+        base: &Image<T>,
+        swizzle: Swizzle,
+        range: Range<u32>,
+    ) -> Result<Vec<(u32, Vec<u8>)>, Error>
+    where
+        D: DataType + Copy,
+        T: Deref<Target = [L]>,
+        L: Deref<Target = [D]>,
+    {
+        base.check_len()?;
+
+        if range.end > mip_level_count(base.extents) {
+            return Err(Error::BadParam);
+        }
+
+        let mut levels = Vec::with_capacity(range.len());
+        let mut current: Option<Image<Vec<Vec<D>>>> = None;
+
+        for level in 0..range.end {
+            if level > 0 {
+                current = Some(match &current {
+                    Some(image) => image.downsampled(),
+                    None => base.downsampled(),
+                });
+            }
+
+            if range.contains(&level) {
+                let data = match &current {
+                    Some(image) => self.compress(image, swizzle)?,
+                    None => self.compress(base, swizzle)?,
+                };
+                levels.push((level, data));
+            }
+        }
+
+        Ok(levels)
+    }
+
+    /// Compress an image with straight alpha as a premultiplied-alpha texture. The RGB components
+    /// are multiplied by alpha before compressing, and the error is weighted by alpha (as with
+    /// `Flags::USE_ALPHA_WEIGHT`, which is enabled for this call if the context doesn't already
+    /// use it). The decoded data is premultiplied, so it must either be blended as such or be
+    /// divided by alpha after decoding.
+    pub fn compress_premultiplied<D, T, L>(
+        &mut self,
+        image: &Image<T>,
+        swizzle: Swizzle,
+    ) -> Result<Vec<u8>, Error>
+    where
+        D: DataType + Copy,
+        T: Deref<Target = [L]>,
+        L: Deref<Target = [D]>,
+    {
+        image.check_len()?;
+
+        let premultiplied = image.map_texels(|[r, g, b, a]| {
+            let alpha = a.to_f32();
+            [
+                D::from_f32(r.to_f32() * alpha),
+                D::from_f32(g.to_f32() * alpha),
+                D::from_f32(b.to_f32() * alpha),
+                a,
+            ]
+        });
+
+        if self.config.builder.flags.contains(Flags::USE_ALPHA_WEIGHT) {
+            self.compress(&premultiplied, swizzle)
+        } else {
+            let mut builder = self.config.builder.clone();
+            builder.flags(builder.flags | Flags::USE_ALPHA_WEIGHT);
+            Context::new(builder.build()?)?.compress(&premultiplied, swizzle)
+        }
+    }
+
+    /// Compress the given image like `compress`, but store the blocks in the given order. Use
+    /// `Context::decompress_tiled_order` or `reorder_blocks` to read the data back.
+    pub fn compress_tiled_order<D, T, L>(
+        &mut self,
+        image: &Image<T>,
+        swizzle: Swizzle,
+        order: BlockOrder,
+    ) -> Result<Vec<u8>, Error>
+    where
+        D: DataType,
+        T: Deref<Target = [L]>,
+        L: Deref<Target = [D]>,
+    {
+        let data = self.compress(image, swizzle)?;
+
+        match order {
+            BlockOrder::RowMajor => Ok(data),
+            _ => reorder_blocks(
+                &data,
+                self.block_size(),
+                image.extents,
+                BlockOrder::RowMajor,
+                order,
+            ),
+        }
+    }
+
+    /// Compress the given image like `compress`, and also return the original RGBA values of the
+    /// texels at the coordinates in `exact`. ASTC can't guarantee that any particular texel
+    /// survives compression, so this lets applications patch critical texels (such as in icons
+    /// or text) back in after decoding. The values are taken from `image` before the swizzle is
+    /// applied. Returns `Error::BadParam` if any of the coordinates are outside the image.
+    pub fn compress_with_exact<D, T, L>(
+        &mut self,
+        image: &Image<T>,
+        swizzle: Swizzle,
+        exact: &[Extents],
+    ) -> Result<(Vec<u8>, ExactTexels<D>), Error>
+    where
+        D: DataType + Copy,
+        T: Deref<Target = [L]>,
+        L: Deref<Target = [D]>,
+    {
+        image.check_len()?;
+
+        let texels = exact
+            .iter()
+            .map(|&coord| {
+                if coord.x >= image.extents.x
+                    || coord.y >= image.extents.y
+                    || coord.z >= image.extents.z
+                {
+                    return Err(Error::BadParam);
+                }
+
+                let i = ((coord.y * image.extents.x + coord.x) * 4) as usize;
+                let texel = &image.data[coord.z as usize][i..i + 4];
+                Ok((coord, [texel[0], texel[1], texel[2], texel[3]]))
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Ok((self.compress(image, swizzle)?, texels))
+    }
+
+    /// Compress the given image like `compress`, but encode the blocks in `overrides` (keyed by
+    /// their row-major block index) as described by their `BlockOverride` instead of using the
+    /// encoding picked by the encoder. This is an advanced hook for experimenting with the
+    /// encoding. Overrides that can't be honored, currently `BlockOverride::Partitions`, fall back
+    /// to the normal encoding of the block. Returns `Error::BadParam` if any of the indices are
+    /// outside the image.
+    pub fn compress_with_overrides<D, T, L>(
+        &mut self,
+        image: &Image<T>,
+        swizzle: Swizzle,
+        overrides: &HashMap<usize, BlockOverride>,
+    ) -> Result<Vec<u8>, Error>
+    where
+        D: DataType + Copy,
+        T: Deref<Target = [L]>,
+        L: Deref<Target = [D]>,
+    {
+        let block = self.config.block_size();
+        let block_count = self.block_size().grid(image.extents);
+        let block_count = (block_count.x * block_count.y * block_count.z) as usize;
+        if overrides.keys().any(|&index| index >= block_count) {
+            return Err(Error::BadParam);
+        }
+
+        let hdr = self.config.builder.profile.requires_float_input();
+        let mut data = self.compress(image, swizzle)?;
+
+        for (&index, &block_override) in overrides {
+            match block_override {
+                BlockOverride::Constant => {
+                    let (origin, size) = block_bounds(index, block, image.extents);
+                    let mut sum = [0.0f64; 4];
+
+                    for z in origin.z..origin.z + size.z {
+                        let layer = &image.data[z as usize];
+                        for y in origin.y..origin.y + size.y {
+                            for x in origin.x..origin.x + size.x {
+                                let i = ((y * image.extents.x + x) * 4) as usize;
+                                let texel = swizzle.apply(&layer[i..i + 4]);
+                                for (sum, component) in sum.iter_mut().zip(texel.iter()) {
+                                    *sum += *component as f64;
+                                }
+                            }
+                        }
+                    }
+
+                    let texels = (size.x * size.y * size.z) as f64;
+                    let block = if hdr {
+                        constant_block_hdr(sum.map(|sum| {
+                            half::f16::from_f64((sum / texels).clamp(0.0, half::f16::MAX.into()))
+                        }))
+                    } else {
+                        constant_block(sum.map(|sum| {
+                            ((sum / texels).clamp(0.0, 1.0) * u16::MAX as f64).round() as u16
+                        }))
+                    };
+
+                    let start = index * Extents::block_byte_cost();
+                    data[start..start + Extents::block_byte_cost()].copy_from_slice(&block);
+                }
+                BlockOverride::Partitions(_) => {}
+            }
+        }
+
+        Ok(data)
+    }
+
+    /// Compress the given image like `compress`, but with the alpha of `swizzle` replaced by
+    /// `Selector::One`, so the alpha of the image is ignored and always decodes as exactly 1. This
+    /// is for images whose alpha is noise that should be discarded, and unlike
+    /// `Context::compress_auto_opaque` it doesn't check the alpha first. The bits that would have
+    /// stored the alpha are used for the color instead.
+    pub fn compress_opaque<D, T, L>(
+        &mut self,
+        image: &Image<T>,
+        mut swizzle: Swizzle,
+    ) -> Result<Vec<u8>, Error>
+    where
+        D: DataType,
+        T: Deref<Target = [L]>,
+        L: Deref<Target = [D]>,
+    {
+        swizzle.a = Selector::One;
+        self.compress(image, swizzle)
+    }
+
+    /// Compress the given image like `compress`, but if the component that `swizzle` selects for
+    /// the alpha is 1 for every texel, replace it with `Selector::One`. This tells the encoder
+    /// up front that the image is opaque instead of relying on it to notice, and guarantees that
+    /// the alpha decodes as exactly 1. Returns the compressed data along with the swizzle to
+    /// decompress it with, which is `Swizzle::rgb1` if the image was found to be opaque and
+    /// `Swizzle::rgba` otherwise.
+    pub fn compress_auto_opaque<D, T, L>(
+        &mut self,
+        image: &Image<T>,
+        mut swizzle: Swizzle,
+    ) -> Result<(Vec<u8>, Swizzle), Error>
+    where
+        D: DataType + Copy,
+        T: Deref<Target = [L]>,
+        L: Deref<Target = [D]>,
+    {
+        image.check_len()?;
+
+        let alpha = match swizzle.a {
+            Selector::Red => Some(0),
+            Selector::Green => Some(1),
+            Selector::Blue => Some(2),
+            Selector::Alpha => Some(3),
+            Selector::Zero | Selector::One | Selector::Z => None,
+        };
+
+        let opaque = alpha.map_or(false, |alpha| {
+            image.data.iter().all(|layer| {
+                layer
+                    .chunks_exact(4)
+                    .all(|texel| texel[alpha].to_f32() == 1.0)
+            })
+        });
+
+        if opaque {
+            swizzle.a = Selector::One;
+            Ok((self.compress(image, swizzle)?, Swizzle::rgb1()))
+        } else {
+            Ok((self.compress(image, swizzle)?, Swizzle::rgba()))
+        }
+    }
+
+    /// Compress an HDR image as RGBM, which stores the RGB divided by a per-texel multiplier in
+    /// the RGB components and the multiplier in the alpha, so HDR content can be used with the
+    /// LDR formats. The scale of the multiplier is picked from the brightest RGB component in the
+    /// image, and is returned along with the compressed data; the HDR color is decoded as
+    /// `rgb * a * scale`. The swizzle is applied to the image before the multiplier is computed,
+    /// so it selects the RGB that is stored, and the alpha it selects is discarded. Returns
+    /// `Error::BadSwizzle` if the swizzle uses `Selector::Z`.
+    ///
+    /// The data is compressed as `Profile::LdrRgba` with `Flags::MAP_RGBM` and otherwise the
+    /// settings of this context, using a temporary context.
+    pub fn compress_rgbm_auto<T, L>(
+        &mut self,
+        image: &Image<T>,
+        swizzle: Swizzle,
+    ) -> Result<(Vec<u8>, f32), Error>
+    where
+        T: Deref<Target = [L]>,
+        L: Deref<Target = [f32]>,
+    {
+        image.check_len()?;
+
+        if [swizzle.r, swizzle.g, swizzle.b, swizzle.a].contains(&Selector::Z) {
+            return Err(Error::BadSwizzle);
+        }
+
+        let swizzled = image.map_texels(|texel| swizzle.apply(&texel));
+
+        let max = swizzled
+            .data
+            .iter()
+            .flat_map(|layer| layer.chunks_exact(4))
+            .flat_map(|texel| texel[..3].iter().copied())
+            .fold(0.0f32, f32::max);
+        let scale = if max > 0.0 { max } else { 1.0 };
+
+        let rgbm = swizzled.map_texels(|[r, g, b, _]| {
+            // Round the multiplier up to the next 8-bit step so dividing by it never overflows
+            // the LDR range, and keep it above zero so the color survives.
+            let m = ((r.max(g).max(b) / scale).clamp(0.0, 1.0) * 255.0)
+                .ceil()
+                .max(1.0)
+                / 255.0;
+            let divide = |component: f32| (component / (m * scale)).clamp(0.0, 1.0);
+            [divide(r), divide(g), divide(b), m]
+        });
+
+        let mut builder = self.config.builder.clone().with_profile(Profile::LdrRgba);
+        builder.flags.insert(Flags::MAP_RGBM);
+        let mut config = builder.build()?;
+        config.inner.rgbm_m_scale = scale;
+
+        let data = Context::new(config)?.compress(&rgbm, Swizzle::rgba())?;
+
+        Ok((data, scale))
+    }
+
+    /// Compress 2-component luma-alpha data, given as interleaved `[L, A]` pairs with layers stored
+    /// one after another. The data is expanded to RGBA with the luma in the color components and
+    /// the alpha in the alpha component, and compressed with `swizzle`. Returns the compressed data
+    /// along with `Swizzle::rrrg`, which decodes the data back to luma-alpha when `swizzle` moves
+    /// the alpha into the green component, e.g. `r: Red, g: Alpha, b: Zero, a: One`. Returns
+    /// `Error::BadParam` if `la` doesn't hold exactly two components per texel.
+    pub fn compress_la<D, T>(
+        &mut self,
+        la: &T,
+        extents: Extents,
+        swizzle: Swizzle,
+    ) -> Result<(Vec<u8>, Swizzle), Error>
+    where
+        D: DataType + Copy,
+        T: AsRef<[D]> + ?Sized,
+    {
+        let la = la.as_ref();
+        let layer_len = extents.x as usize * extents.y as usize * 2;
+        if la.len() != layer_len * extents.z as usize {
+            return Err(Error::BadParam);
+        }
+
+        let image = Image {
+            extents,
+            data: la
+                .chunks_exact(layer_len.max(1))
+                .map(|layer| {
+                    layer
+                        .chunks_exact(2)
+                        .flat_map(|texel| [texel[0], texel[0], texel[0], texel[1]])
+                        .collect::<Vec<D>>()
+                })
+                .collect::<Vec<_>>(),
+        };
+
+        let data = self.compress(&image, swizzle)?;
+
+        Ok((data, Swizzle::rrrg()))
+    }
+
+    /// Decompress an image into a pre-existing buffer. The metadata (size and border padding) must
+    /// already be set and enough space must be reserved in `out.data` for the output pixels (RGBA).
+    /// Returns `Error::BadBlockSize` if the length of `data` doesn't match the extents and the
+    /// block size of this context.
+    pub fn decompress_into<D, T, L>(
+        &mut self,
+        data: &[u8],
+        out: &mut Image<T>,
+        swizzle: Swizzle,
+    ) -> Result<(), Error>
+    where
+        D: DataType,
+        T: DerefMut<Target = [L]>,
+        L: DerefMut<Target = [D]>,
+    {
+        self.check_data_len(data, out.extents)?;
+
+        let mut image_data_pointers = out
+            .data
+            .iter_mut()
+            .map(|layer| layer.as_mut_ptr() as *mut c_void)
+            .collect::<Vec<_>>();
+        let mut image_sys = astcenc_sys::astcenc_image {
+            dim_x: out.extents.x,
+            dim_y: out.extents.y,
+            dim_z: out.extents.z,
+            data_type: D::TYPE.into_sys(),
+            data: image_data_pointers.as_mut_ptr(),
+        };
+
+        error_code_to_result(unsafe {
+            astcenc_sys::astcenc_decompress_image(
+                self.inner.as_mut(),
+                data.as_ptr(),
+                data.len(),
+                &mut image_sys,
+                &swizzle.into_sys(),
+                0,
+            )
+        })
+    }
+
+    /// Decompress an image into a buffer the caller already owns, with the layers stored one after
+    /// another in a single slice. Nothing is allocated for 2D images, which makes this suitable
+    /// for embedded targets and for verifying data in place; for 3D images only a table of
+    /// pointers to the layers is allocated. Returns `Error::BadParam` if the buffer doesn't hold
+    /// exactly one RGBA texel per texel of `image.extents`, and `Error::BadBlockSize` if the
+    /// length of `data` doesn't match the extents and the block size of this context.
+    pub fn decompress_in_place<D>(
+        &mut self,
+        data: &[u8],
+        image: &mut Image<&mut [D]>,
+        swizzle: Swizzle,
+    ) -> Result<(), Error>
+    where
+        D: DataType,
+    {
+        let Extents { x, y, z } = image.extents;
+        let layer_len = x as usize * y as usize * 4;
+        if image.data.len() != layer_len * z as usize {
+            return Err(Error::BadParam);
+        }
+
+        self.check_data_len(data, image.extents)?;
+
+        let base = image.data.as_mut_ptr();
+        let mut single = [base as *mut c_void];
+        let mut layers;
+        let pointers = if z == 1 {
+            &mut single[..]
+        } else {
+            layers = (0..z as usize)
+                .map(|layer| unsafe { base.add(layer * layer_len) as *mut c_void })
+                .collect::<Vec<_>>();
+            &mut layers[..]
+        };
+
+        let mut image_sys = astcenc_sys::astcenc_image {
+            dim_x: x,
+            dim_y: y,
+            dim_z: z,
+            data_type: D::TYPE.into_sys(),
+            data: pointers.as_mut_ptr(),
+        };
+
+        error_code_to_result(unsafe {
+            astcenc_sys::astcenc_decompress_image(
+                self.inner.as_mut(),
+                data.as_ptr(),
+                data.len(),
+                &mut image_sys,
+                &swizzle.into_sys(),
+                0,
+            )
+        })
+    }
+
+    /// Decompress an image. The metadata is not stored in the compressed data itself, and should be
+    /// stored as a separate header.
+    ///
+    /// Returns `Error::BadBlockSize` if the length of `data` doesn't match the extents and the
+    /// block size of this context, which usually means the data was compressed with a different
+    /// block size and would otherwise decode to garbage.
+    pub fn decompress<D>(
+        &mut self,
+        data: &[u8],
+        extents: Extents,
+        swizzle: Swizzle,
+    ) -> Result<Image<Vec<Vec<D>>>, Error>
+    where
+        D: DataType,
+    {
+        self.check_data_len(data, extents)?;
+
+        let size_2d = (extents.x * extents.y * 4) as usize;
+        let mut out = Image {
+            extents,
+            data: (0..extents.z)
+                .map(|_| Vec::with_capacity(size_2d))
+                .collect::<Vec<Vec<D>>>(),
+        };
+
+        let mut image_data_pointers = out
+            .data
+            .iter_mut()
+            .map(|layer| layer.as_mut_ptr() as *mut c_void)
+            .collect::<Vec<_>>();
+        let mut image_sys = astcenc_sys::astcenc_image {
+            dim_x: out.extents.x,
+            dim_y: out.extents.y,
+            dim_z: out.extents.z,
+            data_type: D::TYPE.into_sys(),
+            data: image_data_pointers.as_mut_ptr(),
+        };
+
+        error_code_to_result(unsafe {
+            astcenc_sys::astcenc_decompress_image(
+                self.inner.as_mut(),
+                data.as_ptr(),
+                data.len(),
+                &mut image_sys,
+                &swizzle.into_sys(),
+                0,
+            )
+        })?;
+
+        for layer in &mut out.data {
+            unsafe { layer.set_len(size_2d) };
+        }
+
+        Ok(out)
+    }
+
+    /// Decompress an image whose blocks are stored in the given order, such as the output of
+    /// `Context::compress_tiled_order`.
+    pub fn decompress_tiled_order<D>(
+        &mut self,
+        data: &[u8],
+        extents: Extents,
+        swizzle: Swizzle,
+        order: BlockOrder,
+    ) -> Result<Image<Vec<Vec<D>>>, Error>
+    where
+        D: DataType,
+    {
+        match order {
+            BlockOrder::RowMajor => self.decompress(data, extents, swizzle),
+            _ => {
+                let data = reorder_blocks(
+                    data,
+                    self.block_size(),
+                    extents,
+                    order,
+                    BlockOrder::RowMajor,
+                )?;
+                self.decompress(&data, extents, swizzle)
+            }
+        }
+    }
+
+    /// Decompress an approximate, downscaled version of a 2D image that is at most `max_dim`
+    /// texels wide and high, for thumbnails. Images that already fit are decompressed in full.
+    /// Otherwise only a coarse subsample of the blocks is decoded, spaced evenly in each
+    /// dimension, and each decoded block is averaged down to a single texel, which is much faster
+    /// than decompressing the whole image and downscaling it, but aliases on fine detail.
+    ///
+    /// Returns `Error::BadBlockSize` if `block` isn't the block size of this context or the
+    /// data has the wrong length, and `Error::BadParam` for 3D images or a `max_dim` of 0.
+    pub fn decompress_thumbnail(
+        &mut self,
+        data: &[u8],
+        block: BlockSize,
+        extents: Extents,
+        max_dim: u32,
+        swizzle: Swizzle,
+    ) -> Result<Image<Vec<Vec<u8>>>, Error> {
+        if block != self.block_size() {
+            return Err(Error::BadBlockSize);
+        }
+        let block = block.extents();
+        self.check_data_len(data, extents)?;
+
+        if extents.z != 1 || max_dim == 0 {
+            return Err(Error::BadParam);
+        }
+
+        if extents.x <= max_dim && extents.y <= max_dim {
+            return self.decompress(data, extents, swizzle);
+        }
+
+        let grid = extents.in_blocks(block);
+        let step = (grid.x.max(grid.y) + max_dim - 1) / max_dim;
+        let thumbnail = Extents::new((grid.x + step - 1) / step, (grid.y + step - 1) / step);
+
+        let mut layer = Vec::with_capacity((thumbnail.x * thumbnail.y * 4) as usize);
+        for y in 0..thumbnail.y {
+            for x in 0..thumbnail.x {
+                let index = ((y * step) * grid.x + x * step) as usize;
+                let start = index * Extents::block_byte_cost();
+                let decoded = self.decompress::<u8>(
+                    &data[start..start + Extents::block_byte_cost()],
+                    block,
+                    swizzle,
+                )?;
+
+                // Only average the texels that are inside the image.
+                let (_, size) = block_bounds(index, block, extents);
+                let mut sum = [0u32; 4];
+                for by in 0..size.y {
+                    for bx in 0..size.x {
+                        let i = ((by * block.x + bx) * 4) as usize;
+                        for (sum, &component) in sum.iter_mut().zip(&decoded.data[0][i..i + 4]) {
+                            *sum += component as u32;
+                        }
+                    }
+                }
+
+                let texels = size.x * size.y;
+                layer.extend(sum.iter().map(|&sum| ((sum + texels / 2) / texels) as u8));
+            }
+        }
+
+        Ok(Image {
+            extents: thumbnail,
+            data: vec![layer],
+        })
+    }
+
+    /// Decompress only the alpha channel of an image, such as a shadow or opacity mask, returning
+    /// one component per texel with layers stored one after another. The image is decoded one
+    /// row of blocks at a time with `StreamDecompressor`, so the full RGBA image is never held in
+    /// memory.
+    pub fn decompress_alpha_mask<D>(
+        &mut self,
+        data: &[u8],
+        extents: Extents,
+    ) -> Result<Vec<D>, Error>
+    where
+        D: DataType + Copy,
+    {
+        let block = self.config.block_size();
+        let grid = block_grid(data.len(), self.block_size(), extents)?;
+
+        let mut mask = vec![D::from_f32(0.0); (extents.x * extents.y * extents.z) as usize];
+        let rows = StreamDecompressor::<D>::new(self, data, extents, Swizzle::rgba());
+
+        for (row, texels) in rows.enumerate() {
+            let texels = texels?;
+            let (block_y, block_z) = (row as u32 % grid.y, row as u32 / grid.y);
+            let row_len = extents.x as usize;
+            let height = (extents.y - block_y * block.y).min(block.y);
+
+            for (i, texels) in texels.chunks_exact(row_len * 4).enumerate() {
+                let z = block_z * block.z + i as u32 / height;
+                let y = block_y * block.y + i as u32 % height;
+                let start = (z * extents.y + y) as usize * row_len;
+
+                for (alpha, texel) in mask[start..start + row_len]
+                    .iter_mut()
+                    .zip(texels.chunks_exact(4))
+                {
+                    *alpha = texel[3];
+                }
+            }
+        }
+
+        Ok(mask)
+    }
+
+    /// Decompress an image to `writer` as raw RGBA components in row-major order with layers
+    /// stored one after another, the layout read by `Image::from_reader`. The image is decoded
+    /// one row of blocks at a time with `StreamDecompressor` and each row is written as soon as
+    /// it is decoded, so huge textures can be converted with bounded memory. For 3D blocks the
+    /// rows covering one layer of blocks have to be buffered to write the layers in order.
+    ///
+    /// Returns `Error::BadBlockSize` if `block` isn't the block size of this context or the
+    /// data has the wrong length.
+    pub fn decompress_to_writer<D, W>(
+        &mut self,
+        data: &[u8],
+        block: BlockSize,
+        extents: Extents,
+        swizzle: Swizzle,
+        mut writer: W,
+    ) -> Result<(), Error>
+    where
+        D: DataType + Copy,
+        W: Write,
+    {
+        if block != self.block_size() {
+            return Err(Error::BadBlockSize);
+        }
+        let block = block.extents();
+        self.check_data_len(data, extents)?;
+
+        let grid = extents.in_blocks(block);
+        let mut slab = Vec::new();
+        let rows = StreamDecompressor::<D>::new(self, data, extents, swizzle);
+
+        for (row, texels) in rows.enumerate() {
+            let texels = texels?;
+
+            if block.z == 1 {
+                writer.write_all(D::as_u8s(&texels))?;
+                continue;
+            }
+
+            slab.push(texels);
+            if (row as u32 + 1) % grid.y != 0 {
+                continue;
+            }
+
+            // Every row of blocks in the slab covers the same layers, so write the part of each
+            // row that belongs to the first layer, then the second, and so on.
+            let block_z = row as u32 / grid.y;
+            let depth = (extents.z - block_z * block.z).min(block.z) as usize;
+            for layer in 0..depth {
+                for texels in &slab {
+                    let layer_len = texels.len() / depth;
+                    writer.write_all(D::as_u8s(
+                        &texels[layer * layer_len..(layer + 1) * layer_len],
+                    ))?;
+                }
+            }
+            slab.clear();
+        }
+
+        Ok(())
+    }
+
+    /// Decompress an image to linear `f32` components. ASTC stores sRGB data still encoded with
+    /// the sRGB transfer function (GPUs decode it when sampling), so for the `LdrSrgb` profile
+    /// the inverse sRGB transfer function is applied to the red, green and blue components of
+    /// the output. Alpha is always stored linearly and is left as is, and the data of every other
+    /// profile is already linear.
+    pub fn decompress_linear(
+        &mut self,
+        data: &[u8],
+        extents: Extents,
+        swizzle: Swizzle,
+    ) -> Result<Image<Vec<Vec<f32>>>, Error> {
+        let mut out = self.decompress::<f32>(data, extents, swizzle)?;
+
+        if self.config.builder.profile == Profile::LdrSrgb {
+            let to_linear = |c: f32| {
+                if c <= 0.04045 {
+                    c / 12.92
+                } else {
+                    ((c + 0.055) / 1.055).powf(2.4)
+                }
+            };
+
+            for texel in out
+                .data
+                .iter_mut()
+                .flat_map(|layer| layer.chunks_exact_mut(4))
+            {
+                for component in &mut texel[..3] {
+                    *component = to_linear(*component);
+                }
+            }
+        }
+
+        Ok(out)
+    }
+
+    /// Decompress an image to `f32` components clamped to `min..=max`, for example to fit HDR data
+    /// into the range of a display for previewing. The output is clamped in place, so no second
+    /// image is allocated.
+    ///
+    /// Returns `Error::BadParam` if `min` is greater than `max` or either is NaN.
+    pub fn decompress_clamped(
+        &mut self,
+        data: &[u8],
+        extents: Extents,
+        swizzle: Swizzle,
+        min: f32,
+        max: f32,
+    ) -> Result<Image<Vec<Vec<f32>>>, Error> {
+        if min.is_nan() || max.is_nan() || min > max {
+            return Err(Error::BadParam);
+        }
+
+        let mut out = self.decompress::<f32>(data, extents, swizzle)?;
+        for component in out.data.iter_mut().flat_map(|layer| layer.iter_mut()) {
+            *component = component.clamp(min, max);
+        }
+
+        Ok(out)
+    }
+
+    /// Decompress a 2-component normal map that was compressed with the `rrrg` swizzle, returning
+    /// X and Y in the red and green components and Z, reconstructed as `sqrt(1 - x² - y²)`, in
+    /// the blue component. Alpha is set to 1.
+    pub fn decompress_normal<D>(
+        &mut self,
+        data: &[u8],
+        extents: Extents,
+    ) -> Result<Image<Vec<Vec<D>>>, Error>
+    where
+        D: DataType,
+    {
+        self.decompress(data, extents, Swizzle::raz1())
+    }
+
+    /// Lazily decompress an image one block at a time, yielding the texel origin of each block and
+    /// its RGBA data in row-major order. Blocks at the right, bottom and back edges of the image
+    /// only include the texels inside the image. Blocks are decoded as they are requested, so
+    /// only as much work is done as the caller consumes.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `block` isn't the block size of this context or the data has the wrong length
+    /// for the extents. Both are checked before the iterator is returned.
+    pub fn decode_iter<'a, D>(
+        &'a mut self,
+        data: &'a [u8],
+        block: BlockSize,
+        extents: Extents,
+        swizzle: Swizzle,
+    ) -> impl Iterator<Item = (Extents, Vec<D>)> + 'a
+    where
+        D: DataType + Copy,
+    {
+        assert_eq!(
+            block,
+            self.block_size(),
+            "block size doesn't match the context"
+        );
+        assert!(
+            self.is_compatible_with(data, extents),
+            "data length doesn't match the extents"
+        );
+        let block = block.extents();
+
+        (0..data.len() / Extents::block_byte_cost()).map(move |index| {
+            let (origin, size) = block_bounds(index, block, extents);
+
+            let start = index * Extents::block_byte_cost();
+            let texels = self
+                .decompress::<D>(
+                    &data[start..start + Extents::block_byte_cost()],
+                    size,
+                    swizzle,
+                )
+                .expect("decompressing a single block of validated data failed")
+                .data
+                .concat();
+
+            (origin, texels)
+        })
+    }
+
+    /// Decompress an image into four planar buffers, one per component (R, G, B and A, after
+    /// the swizzle has been applied). Each plane holds one element per texel, with layers stored
+    /// one after another.
+    pub fn decompress_planar<D>(
+        &mut self,
+        data: &[u8],
+        extents: Extents,
+        swizzle: Swizzle,
+    ) -> Result<[Vec<D>; 4], Error>
+    where
+        D: DataType + Copy,
+    {
+        let image = self.decompress::<D>(data, extents, swizzle)?;
+
+        let num_texels = extents.x as usize * extents.y as usize * extents.z as usize;
+        let mut planes = [(); 4].map(|_| Vec::with_capacity(num_texels));
+
+        for texel in image.data.iter().flat_map(|layer| layer.chunks_exact(4)) {
+            for (plane, &component) in planes.iter_mut().zip(texel) {
+                plane.push(component);
+            }
+        }
+
+        Ok(planes)
+    }
+
+    /// Count the kinds of blocks in data compressed with the block size of this context, for
+    /// checking how settings such as `ConfigBuilder::plane_separation_threshold` affect the
+    /// encodings the encoder picks. Returns `Error::BadParam` if the length of `data` isn't a
+    /// multiple of the block byte cost.
+    pub fn compress_stats(&mut self, data: &[u8]) -> Result<CompressStats, Error> {
+        if data.len() % Extents::block_byte_cost() != 0 {
+            return Err(Error::BadParam);
+        }
+
+        let mut stats = CompressStats::default();
+        for compressed_block in data.chunks_exact(Extents::block_byte_cost()) {
+            let info = self.block_info(compressed_block)?;
+
+            stats.blocks += 1;
+            if info.is_error_block {
+                stats.error_blocks += 1;
+                stats.partitions[0] += 1;
+                continue;
+            }
+
+            if info.is_constant_block {
+                stats.constant_blocks += 1;
+                stats.partitions[0] += 1;
+                continue;
+            }
+
+            if info.is_dual_plane_block {
+                stats.dual_plane_blocks += 1;
+            }
+            stats.partitions[(info.partition_count as usize).min(4)] += 1;
+        }
+
+        Ok(stats)
+    }
+
+    fn block_info(&mut self, block: &[u8]) -> Result<astcenc_sys::astcenc_block_info, Error> {
+        let mut info: MaybeUninit<astcenc_sys::astcenc_block_info> = MaybeUninit::uninit();
+
+        error_code_to_result(unsafe {
+            astcenc_sys::astcenc_get_block_info(
+                self.inner.as_mut(),
+                block.as_ptr(),
+                info.as_mut_ptr(),
+            )
+        })?;
+
+        Ok(unsafe { info.assume_init() })
+    }
+
+    /// Reset the internal state of the context so it can be used for the next image. This is
+    /// done automatically at the end of every compression, and decompressions don't leave any
+    /// state behind that needs resetting, so it never has to be called explicitly. It is
+    /// provided for callers that want to be certain a long-lived context starts from a clean
+    /// state, and is safe to call at any time, including on contexts created with
+    /// `Flags::DECOMPRESS_ONLY`. If the reset fails the context is reallocated instead, and an
+    /// error is only returned if that fails too.
+    pub fn reset(&mut self) -> Result<(), Error> {
+        let decompress_only = self.config.builder.flags.contains(Flags::DECOMPRESS_ONLY);
+
+        error_code_to_result(unsafe { astcenc_sys::astcenc_decompress_reset(self.inner.as_mut()) })
+            .and_then(|()| {
+                if decompress_only {
+                    return Ok(());
+                }
+
+                error_code_to_result(unsafe {
+                    astcenc_sys::astcenc_compress_reset(self.inner.as_mut())
+                })
+            })
+            .or_else(|_| self.reinit())
+    }
+}
+
+impl Drop for Context {
+    fn drop(&mut self) {
+        unsafe { astcenc_sys::astcenc_context_free(self.inner.as_ptr()) };
+    }
+}
+
+/// A single image to compress with `compress_jobs`, along with the settings to compress it with.
+pub struct CompressJob<T> {
+    /// The configuration to compress the image with.
+    pub config: ConfigBuilder,
+    /// The image to compress.
+    pub image: Image<T>,
+    /// The swizzle to compress the image with.
+    pub swizzle: Swizzle,
+}
+
+/// Compress a batch of images, each with its own config. Jobs with equal configs share a
+/// context, so the cost of creating contexts is only paid once per distinct config. Returns one
+/// result per job, in the same order as the jobs.
+pub fn compress_jobs<D, T, L>(jobs: &[CompressJob<T>]) -> Vec<Result<CompressedImage, Error>>
+where
+    D: DataType,
+    T: Deref<Target = [L]>,
+    L: Deref<Target = [D]>,
+{
+    let mut contexts: Vec<(&ConfigBuilder, Result<Context, Error>)> = Vec::new();
+
+    jobs.iter()
+        .map(|job| -> Result<CompressedImage, Error> {
+            let index = match contexts
+                .iter()
+                .position(|(config, _)| **config == job.config)
+            {
+                Some(index) => index,
+                None => {
+                    let context = job.config.clone().build().and_then(Context::new);
+                    contexts.push((&job.config, context));
+                    contexts.len() - 1
+                }
+            };
+            let context = contexts[index].1.as_mut().map_err(|error| *error)?;
+
+            Ok(CompressedImage {
+                block: context.block_size(),
+                extents: job.image.extents,
+                data: context.compress(&job.image, job.swizzle)?,
+            })
+        })
+        .collect()
+}
+
+/// Compress and decompress each image with the config at the same index and measure the PSNR of
+/// the result (see `psnr`), for quality gates in asset pipelines. Returns the index, PSNR and
+/// whether the PSNR is at least `threshold` for each image, in the same order as the images.
+/// Images with equal configs share a context, as with `compress_jobs`. Returns
+/// `Error::BadParam` if the number of configs and images differ, and the first error hit while
+/// compressing otherwise.
+pub fn verify_batch<D, T, L>(
+    configs: &[ConfigBuilder],
+    images: &[Image<T>],
+    swizzle: Swizzle,
+    threshold: f64,
+) -> Result<Vec<(usize, f64, bool)>, Error>
+where
+    D: DataType + Copy,
+    T: Deref<Target = [L]>,
+    L: Deref<Target = [D]>,
+{
+    if configs.len() != images.len() {
+        return Err(Error::BadParam);
+    }
+
+    let mut contexts: Vec<(&ConfigBuilder, Context)> = Vec::new();
+
+    configs
+        .iter()
+        .zip(images)
+        .enumerate()
+        .map(
+            |(index, (config, image))| -> Result<(usize, f64, bool), Error> {
+                let position = match contexts.iter().position(|(other, _)| *other == config) {
+                    Some(position) => position,
+                    None => {
+                        contexts.push((config, Context::new(config.clone().build()?)?));
+                        contexts.len() - 1
+                    }
+                };
+
+                let (_, quality) = contexts[position].1.compress_verified(image, swizzle)?;
+                Ok((index, quality, quality >= threshold))
+            },
+        )
+        .collect()
+}
+
+/// Compress an image with the smallest block size whose output fits in `max_bytes`, for pipelines
+/// with a fixed size budget per texture. Block sizes are tried in order of increasing volume, so
+/// the result has the highest quality that fits; 3D block sizes are only considered for 3D
+/// images. All other settings are taken from `config_base`. Returns the block size used along with
+/// the compressed data, or `Error::BadParam` if even the largest block size exceeds the budget.
+pub fn compress_to_budget<D, T, L>(
+    config_base: &ConfigBuilder,
+    image: &Image<T>,
+    swizzle: Swizzle,
+    max_bytes: usize,
+) -> Result<(BlockSize, Vec<u8>), Error>
+where
+    D: DataType,
+    T: Deref<Target = [L]>,
+    L: Deref<Target = [D]>,
+{
+    let mut candidates: Vec<BlockSize> = supported_block_sizes()
+        .into_iter()
+        .filter(|block| block.extents().z == 1 || image.extents.z > 1)
+        .collect();
+    candidates.sort_by_key(|block| {
+        let block = block.extents();
+        block.x * block.y * block.z
+    });
+
+    let block = candidates
+        .into_iter()
+        .find(|&block| compressed_size(block, image.extents) <= max_bytes)
+        .ok_or(Error::BadParam)?;
+
+    let mut ctx = Context::new(config_base.clone().with_block_size(block).build()?)?;
+
+    Ok((block, ctx.compress(image, swizzle)?))
+}
+
+/// A set of contexts for compressing and decompressing with any profile and block size, for
+/// services that handle many kinds of textures. A context is created the first time each
+/// combination of profile and block size is used, and kept for reuse by later calls.
+#[derive(Default)]
+pub struct MultiProfileContext {
+    builder: ConfigBuilder,
+    contexts: HashMap<(Profile, BlockSize), Context>,
+}
+
+impl MultiProfileContext {
+    /// Create an empty set of contexts. The contexts are created with the settings from
+    /// `builder`, apart from the profile and block size which are given with each call.
+    pub fn new(builder: ConfigBuilder) -> Self {
+        Self {
+            builder,
+            contexts: HashMap::new(),
+        }
+    }
+
+    fn context(&mut self, profile: Profile, block: BlockSize) -> Result<&mut Context, Error> {
+        use std::collections::hash_map::Entry;
+
+        match self.contexts.entry((profile, block)) {
+            Entry::Occupied(entry) => Ok(entry.into_mut()),
+            Entry::Vacant(entry) => {
+                let config = self
+                    .builder
+                    .clone()
+                    .with_profile(profile)
+                    .with_block_size(block)
+                    .build()?;
+                Ok(entry.insert(Context::new(config)?))
+            }
+        }
+    }
+
+    /// Compress an image with the given profile and block size, see `Context::compress`.
+    pub fn compress<D, T, L>(
+        &mut self,
+        profile: Profile,
+        block: BlockSize,
+        image: &Image<T>,
+        swizzle: Swizzle,
+    ) -> Result<Vec<u8>, Error>
+    where
+        D: DataType,
+        T: Deref<Target = [L]>,
+        L: Deref<Target = [D]>,
+    {
+        self.context(profile, block)?.compress(image, swizzle)
+    }
+
+    /// Decompress an image that was compressed with the given profile and block size, see
+    /// `Context::decompress`.
+    pub fn decompress<D>(
+        &mut self,
+        profile: Profile,
+        block: BlockSize,
+        data: &[u8],
+        extents: Extents,
+        swizzle: Swizzle,
+    ) -> Result<Image<Vec<Vec<D>>>, Error>
+    where
+        D: DataType,
+    {
+        self.context(profile, block)?
+            .decompress(data, extents, swizzle)
+    }
+}
+
+/// The component types that images compressed with the given profile can use. The HDR profiles
+/// only accept the floating-point types, since `Type::U8` can't represent HDR values, while the
+/// LDR profiles accept every type. `Context::compress` returns `Error::BadParam` for images with
+/// any other type.
+pub fn supported_types(profile: Profile) -> &'static [Type] {
+    match profile {
+        Profile::HdrRgba | Profile::HdrRgbLdrA => &[Type::F16, Type::F32],
+        Profile::LdrRgba | Profile::LdrSrgb => &[Type::U8, Type::F16, Type::F32],
+    }
+}
+
+/// Every block size supported by ASTC, with the 2D sizes first followed by the 3D sizes, each in
+/// the order used by the GL format enums.
+pub fn supported_block_sizes() -> Vec<BlockSize> {
+    BLOCK_SIZES_2D
+        .iter()
+        .map(|&(x, y)| BlockSize(Extents::new(x, y)))
+        .chain(
+            BLOCK_SIZES_3D
+                .iter()
+                .map(|&(x, y, z)| BlockSize(Extents::new_3d(x, y, z))),
+        )
+        .collect()
+}
+
+/// Every standard ASTC GPU format as its profile, block size and `VkFormat` value, in the order
+/// of the `VkFormat` enum. These are the LDR and sRGB formats for each of the 2D block sizes,
+/// which are the ones in core Vulkan; GL uses the same set of formats.
+pub fn all_gpu_formats() -> Vec<(Profile, BlockSize, u32)> {
+    supported_block_sizes()
+        .into_iter()
+        .flat_map(|block| [Profile::LdrRgba, Profile::LdrSrgb].map(|profile| (profile, block)))
+        .filter_map(|(profile, block)| Some((profile, block, vk_format(profile, block)?)))
+        .collect()
+}
+
+/// The `VkFormat` for data compressed with the given profile and block size, if there is one.
+/// The HDR profiles use the `SFLOAT` formats from `VK_EXT_texture_compression_astc_hdr`, and
+/// there are no formats for 3D block sizes.
+fn vk_format(profile: Profile, block: BlockSize) -> Option<u32> {
+    const VK_FORMAT_ASTC_4X4_UNORM_BLOCK: u32 = 157;
+    const VK_FORMAT_ASTC_4X4_SRGB_BLOCK: u32 = 158;
+    const VK_FORMAT_ASTC_4X4_SFLOAT_BLOCK: u32 = 1000066000;
+
+    let index = BLOCK_SIZES_2D
+        .iter()
+        .position(|&(x, y)| Extents::new(x, y) == block.extents())? as u32;
+
+    Some(match profile {
+        Profile::LdrRgba => VK_FORMAT_ASTC_4X4_UNORM_BLOCK + index * 2,
+        Profile::LdrSrgb => VK_FORMAT_ASTC_4X4_SRGB_BLOCK + index * 2,
+        Profile::HdrRgba | Profile::HdrRgbLdrA => VK_FORMAT_ASTC_4X4_SFLOAT_BLOCK + index,
+    })
+}
+
+/// The profile and block size of an ASTC `VkFormat`, the inverse of `vk_format`. The `SFLOAT`
+/// formats are reported as `Profile::HdrRgba`.
+#[cfg(feature = "ktx")]
+fn from_vk_format(format: u32) -> Option<(Profile, BlockSize)> {
+    all_gpu_formats()
+        .into_iter()
+        .map(|(profile, block, _)| (profile, block))
+        .chain(
+            supported_block_sizes()
+                .into_iter()
+                .map(|block| (Profile::HdrRgba, block)),
+        )
+        .find(|&(profile, block)| vk_format(profile, block) == Some(format))
+}
+
+/// Pick the preset swizzle that best fits the content of an image: `Swizzle::rrr1` for greyscale
+/// images (where the red, green and blue are equal in every texel), `Swizzle::rrrg` for greyscale
+/// images with alpha, `Swizzle::rgb1` for opaque color images and `Swizzle::rgba` otherwise. An
+/// image is opaque if every alpha converts to `1.0` with `DataType::to_f32`. Note that `rrrg`
+/// reads the alpha from the green component, so greyscale images with alpha have to be stored
+/// as luminance-alpha (with the alpha in the green component) before compressing with it.
+pub fn recommend_swizzle<D, T, L>(image: &Image<T>) -> Swizzle
+where
+    D: DataType + Copy,
+    T: Deref<Target = [L]>,
+    L: Deref<Target = [D]>,
+{
+    let texels = || image.data.iter().flat_map(|layer| layer.chunks_exact(4));
+
+    let grey = texels().all(|texel| {
+        let red = texel[0].to_f32();
+        texel[1].to_f32() == red && texel[2].to_f32() == red
+    });
+    let opaque = texels().all(|texel| texel[3].to_f32() == 1.0);
+
+    match (grey, opaque) {
+        (true, true) => Swizzle::rrr1(),
+        (true, false) => Swizzle::rrrg(),
+        (false, true) => Swizzle::rgb1(),
+        (false, false) => Swizzle::rgba(),
+    }
+}
+
+/// Guess the profile a header-less compressed image was compressed with, by inspecting the color
+/// endpoint modes of its blocks. Images with any HDR blocks are guessed as `Profile::HdrRgba`, or
+/// as `Profile::HdrRgbLdrA` if the HDR blocks all have LDR alpha, and all other images as
+/// `Profile::LdrRgba`. This is a best-effort heuristic: LDR and sRGB data can't be told apart,
+/// and HDR images whose content happens to fit in the LDR range may be guessed as LDR (in which
+/// case decoding them as LDR gives the same result anyway). Returns `None` if the data doesn't
+/// match the block size and extents or contains invalid blocks.
+pub fn guess_profile(data: &[u8], block: BlockSize, extents: Extents) -> Option<Profile> {
+    // The color endpoint modes that store HDR color, see section C.2.14 of the Khronos Data
+    // Format Specification.
+    const HDR_ENDPOINT_MODES: [u32; 6] = [2, 3, 7, 11, 14, 15];
+    const HDR_RGB_LDR_ALPHA: u32 = 14;
+
+    if data.len() != compressed_size(block, extents) {
+        return None;
+    }
+
+    let mut ctx = Context::new(
+        ConfigBuilder::new()
+            .with_profile(Profile::HdrRgba)
+            .with_block_size(block)
+            .with_flags(Flags::DECOMPRESS_ONLY)
+            .build()
+            .ok()?,
+    )
+    .ok()?;
+
+    let mut hdr = false;
+    let mut hdr_alpha = false;
+
+    for compressed_block in data.chunks_exact(Extents::block_byte_cost()) {
+        let info = ctx.block_info(compressed_block).ok()?;
+
+        if info.is_error_block {
+            return None;
+        }
+
+        if info.is_constant_block {
+            // Bit 9 of a void-extent block is set when its constant color is HDR.
+            hdr_alpha |= compressed_block[1] & 0x02 != 0;
+            hdr |= compressed_block[1] & 0x02 != 0;
+            continue;
+        }
+
+        for &mode in &info.color_endpoint_modes[..info.partition_count as usize] {
+            if HDR_ENDPOINT_MODES.contains(&mode) {
+                hdr = true;
+                hdr_alpha |= mode != HDR_RGB_LDR_ALPHA;
+            }
+        }
+    }
+
+    match (hdr, hdr_alpha) {
+        (false, _) => Some(Profile::LdrRgba),
+        (true, false) => Some(Profile::HdrRgbLdrA),
+        (true, true) => Some(Profile::HdrRgba),
+    }
+}
+
+/// Predict the PSNR, in decibels, of an LDR image compressed with the given block size, without
+/// compressing it. `image_complexity` is a measure of the detail in the image computed by the
+/// caller, such as the average absolute difference between neighbouring texel components with
+/// components normalized to `0..=1`, so that `0.0` is a flat image.
+///
+/// This is a rough model for previewing trade-offs in user interfaces and not a measurement: the
+/// error is assumed to grow with the complexity and the width of the block, on top of a floor
+/// from quantization. Only comparisons between predictions for the same image are meaningful.
+pub fn estimate_quality(block: BlockSize, image_complexity: f32) -> f32 {
+    let block = block.extents();
+    let texels = (block.x * block.y * block.z) as f32;
+    let rmse = 0.002 + 0.05 * image_complexity.max(0.0) * (texels / 16.0).sqrt();
+    -20.0 * rmse.log10()
+}
+
+/// Recommend how many threads to compress an image with, so schedulers can avoid spreading small
+/// images over threads that would mostly wait. The work is estimated from the number of blocks,
+/// weighted by how slow the preset is, and each thread is given at least a few thousand blocks'
+/// worth of work. The result is between 1 and `std::thread::available_parallelism`.
+///
+/// Like `estimate_quality`, this is a rough model rather than a measurement.
+pub fn recommended_threads(extents: Extents, block: BlockSize, preset: Preset) -> usize {
+    const MIN_WORK_PER_THREAD: f64 = 4096.0;
+
+    let available = std::thread::available_parallelism().map_or(1, |threads| threads.get());
+    let blocks = compressed_size(block, extents) / Extents::block_byte_cost();
+    // The search gets more expensive as the preset goes from fastest (0) to exhaustive (100).
+    let work = blocks as f64 * (1.0 + preset.0 as f64 / 20.0);
+
+    ((work / MIN_WORK_PER_THREAD).ceil() as usize)
+        .max(1)
+        .min(available)
+}
+
+/// The PSNR in decibels for the given mean squared error, relative to a peak of `1.0`.
+fn psnr_from_mse(mse: f64) -> f64 {
+    if mse == 0.0 {
+        f64::INFINITY
+    } else {
+        -10.0 * mse.log10()
+    }
+}
+
+/// The peak signal-to-noise ratio (PSNR) in decibels between two images, such as an image and its
+/// decompressed version. Higher is better, and identical images give `f64::INFINITY`.
+///
+/// The error is computed over all four components converted with `DataType::to_f32`, relative to
+/// a peak of `1.0` (the top of the LDR range). Returns `Error::BadParam` if the extents of the
+/// images differ or don't match their data.
+pub fn psnr<D, T1, L1, T2, L2>(original: &Image<T1>, decoded: &Image<T2>) -> Result<f64, Error>
+where
+    D: DataType + Copy,
+    T1: Deref<Target = [L1]>,
+    L1: Deref<Target = [D]>,
+    T2: Deref<Target = [L2]>,
+    L2: Deref<Target = [D]>,
+{
+    original.check_len()?;
+    decoded.check_len()?;
+    if original.extents != decoded.extents {
+        return Err(Error::BadParam);
+    }
+
+    let mut squared_error = 0.0;
+    let mut components = 0;
+    for (a, b) in original.data.iter().zip(decoded.data.iter()) {
+        for (&a, &b) in a.iter().zip(b.iter()) {
+            squared_error += (a.to_f32() as f64 - b.to_f32() as f64).powi(2);
+            components += 1;
+        }
+    }
+
+    if components == 0 {
+        return Ok(f64::INFINITY);
+    }
+
+    Ok(psnr_from_mse(squared_error / components as f64))
+}
+
+/// An RGBA8 image of the absolute difference between each component of two images, such as an
+/// image and its decompressed version, for seeing where compression artifacts are. Components are
+/// compared after conversion with `DataType::to_f32`, multiplied by `amplify` and clamped to the
+/// `u8` range, so identical images give an all-zero image. The alpha of the result is the
+/// difference in alpha, so view it with blending disabled. Returns `Error::BadParam` if the
+/// extents of the images differ or don't match their data.
+pub fn diff_image<D, T1, L1, T2, L2>(
+    original: &Image<T1>,
+    decoded: &Image<T2>,
+    amplify: f32,
+) -> Result<Image<Vec<Vec<u8>>>, Error>
+where
+    D: DataType + Copy,
+    T1: Deref<Target = [L1]>,
+    L1: Deref<Target = [D]>,
+    T2: Deref<Target = [L2]>,
+    L2: Deref<Target = [D]>,
+{
+    original.check_len()?;
+    decoded.check_len()?;
+    if original.extents != decoded.extents {
+        return Err(Error::BadParam);
+    }
+
+    let data = original
+        .data
+        .iter()
+        .zip(decoded.data.iter())
+        .map(|(a, b)| {
+            a.iter()
+                .zip(b.iter())
+                .map(|(a, b)| {
+                    let difference = (a.to_f32() - b.to_f32()).abs() * amplify;
+                    (difference.clamp(0.0, 1.0) * 255.0).round() as u8
+                })
+                .collect()
+        })
+        .collect();
+
+    Ok(Image {
+        extents: original.extents,
+        data,
+    })
+}
+
+/// The mean structural similarity (SSIM) between the luma of two images, such as an image and its
+/// decompressed version. SSIM tracks perceived quality more closely than PSNR. It is `1.0` for
+/// identical images and lower the more they differ in structure.
+///
+/// Luma is computed with the Rec. 601 weights from the components converted with
+/// `DataType::to_f32`, and SSIM is averaged over 8x8 windows spaced 4 texels apart in each layer
+/// (or a single window covering the layer, for layers smaller than that). Returns
+/// `Error::BadParam` if the extents of the images differ or don't match their data.
+pub fn ssim<D, T1, L1, T2, L2>(original: &Image<T1>, decoded: &Image<T2>) -> Result<f64, Error>
+where
+    D: DataType + Copy,
+    T1: Deref<Target = [L1]>,
+    L1: Deref<Target = [D]>,
+    T2: Deref<Target = [L2]>,
+    L2: Deref<Target = [D]>,
+{
+    const WINDOW: u32 = 8;
+    const STEP: u32 = 4;
+    const C1: f64 = 0.01 * 0.01;
+    const C2: f64 = 0.03 * 0.03;
+
+    original.check_len()?;
+    decoded.check_len()?;
+    if original.extents != decoded.extents {
+        return Err(Error::BadParam);
+    }
+
+    let Extents {
+        x: width,
+        y: height,
+        z: depth,
+    } = original.extents;
+    if width == 0 || height == 0 || depth == 0 {
+        return Ok(1.0);
+    }
+
+    let luma = |layer: &[D]| {
+        layer
+            .chunks_exact(4)
+            .map(|texel| {
+                0.299 * texel[0].to_f32() as f64
+                    + 0.587 * texel[1].to_f32() as f64
+                    + 0.114 * texel[2].to_f32() as f64
+            })
+            .collect::<Vec<f64>>()
+    };
+    let starts = |size: u32| (0..=size.saturating_sub(WINDOW)).step_by(STEP as usize);
+
+    let mut sum = 0.0;
+    let mut windows = 0;
+
+    for (a, b) in original.data.iter().zip(decoded.data.iter()) {
+        let (a, b) = (luma(a), luma(b));
+
+        for y0 in starts(height) {
+            for x0 in starts(width) {
+                let (w, h) = (WINDOW.min(width), WINDOW.min(height));
+                let n = (w * h) as f64;
+                let texels =
+                    (y0..y0 + h).flat_map(|y| (x0..x0 + w).map(move |x| (y * width + x) as usize));
+
+                let (mut mean_a, mut mean_b) = (0.0, 0.0);
+                for i in texels.clone() {
+                    mean_a += a[i];
+                    mean_b += b[i];
+                }
+                mean_a /= n;
+                mean_b /= n;
+
+                let (mut var_a, mut var_b, mut covar) = (0.0, 0.0, 0.0);
+                for i in texels {
+                    var_a += (a[i] - mean_a).powi(2);
+                    var_b += (b[i] - mean_b).powi(2);
+                    covar += (a[i] - mean_a) * (b[i] - mean_b);
+                }
+                var_a /= n;
+                var_b /= n;
+                covar /= n;
+
+                sum += ((2.0 * mean_a * mean_b + C1) * (2.0 * covar + C2))
+                    / ((mean_a.powi(2) + mean_b.powi(2) + C1) * (var_a + var_b + C2));
+                windows += 1;
+            }
+        }
+    }
+
+    Ok(sum / windows as f64)
+}
+
+/// The number of levels in a full mip chain for an image with the given extents, down to and
+/// including the 1x1x1 level.
+pub fn mip_level_count(extents: Extents) -> u32 {
+    32 - extents
+        .x
+        .max(extents.y)
+        .max(extents.z)
+        .max(1)
+        .leading_zeros()
+}
+
+/// The extents of the given level of a mip chain with the given base extents.
+pub fn mip_extents(base: Extents, level: u32) -> Extents {
+    let halve = |size: u32| size.checked_shr(level).unwrap_or(0).max(1);
+    Extents::new_3d(halve(base.x), halve(base.y), halve(base.z))
+}
+
+/// The `(min, max)` range of the component values produced when decompressing an image of the
+/// given profile to the given type. `Type::U8` output is always clamped to `0..=255`, while float
+/// output is `0..=1` for LDR profiles. Float output for HDR profiles is treated as unbounded and
+/// reported as `(f64::MIN, f64::MAX)`, so callers shouldn't clamp it.
+pub fn decoded_range(ty: Type, profile: Profile) -> (f64, f64) {
+    match (ty, profile) {
+        (Type::U8, _) => (0.0, u8::MAX as f64),
+        (Type::F16 | Type::F32, Profile::LdrRgba | Profile::LdrSrgb) => (0.0, 1.0),
+        (Type::F16 | Type::F32, Profile::HdrRgba | Profile::HdrRgbLdrA) => (f64::MIN, f64::MAX),
+    }
+}
+
+/// The number of bytes needed to store an image with the given extents when decompressed to RGBA
+/// components of the given type. This is calculated as a `u64` so that it can't overflow, which
+/// makes it suitable for rejecting oversized images before decompressing them.
+pub fn decoded_size_bytes(extents: Extents, ty: Type) -> u64 {
+    extents.x as u64 * extents.y as u64 * extents.z as u64 * 4 * ty.size() as u64
+}
+
+/// The number of bytes needed to store an image with the given extents when compressed with the
+/// given block size.
+pub fn compressed_size(block: BlockSize, extents: Extents) -> usize {
+    let blocks = extents.in_blocks(block.extents());
+    blocks.x as usize * blocks.y as usize * blocks.z as usize * Extents::block_byte_cost()
+}
+
+/// Like `compressed_size`, but returns `None` instead of overflowing, for extents read from
+/// untrusted file headers.
+pub(crate) fn checked_compressed_size(block: BlockSize, extents: Extents) -> Option<usize> {
+    let blocks = block.grid(extents);
+    (blocks.x as usize)
+        .checked_mul(blocks.y as usize)?
+        .checked_mul(blocks.z as usize)?
+        .checked_mul(Extents::block_byte_cost())
+}
+
+/// The number of blocks along each dimension of an image with the given extents when compressed
+/// with the given block size. Blocks are stored in x-major, then y, then z order. Returns
+/// `Error::BadParam` if `data_len` isn't the length of such a compressed image, which catches
+/// side-channel metadata that doesn't match the data.
+pub fn block_grid(data_len: usize, block: BlockSize, image: Extents) -> Result<Extents, Error> {
+    if data_len != compressed_size(block, image) {
+        return Err(Error::BadParam);
+    }
+
+    Ok(block.grid(image))
+}
+
+/// The largest image extents covered by a grid of blocks of the given size. This is the inverse of
+/// `block_grid` for images whose extents are a multiple of the block size, and an upper bound for
+/// all others.
+pub fn image_from_block_grid(grid: Extents, block: BlockSize) -> Extents {
+    let block = block.extents();
+    Extents::new_3d(grid.x * block.x, grid.y * block.y, grid.z * block.z)
+}
+
+/// Crop a compressed image without re-encoding it, by copying the blocks that cover the box
+/// starting at `origin` with the given `size`. The result is a compressed image with extents
+/// `size`, and decodes to exactly the same texels as that box of the original image.
+///
+/// `origin` must be a multiple of the block size, and `size` must be too unless the box reaches
+/// the edge of the image. Returns `Error::BadParam` if that isn't the case, if the box doesn't fit
+/// in the image, or if `data` isn't the length of an image of extents `full`.
+pub fn crop_blocks(
+    data: &[u8],
+    block: BlockSize,
+    full: Extents,
+    origin: Extents,
+    size: Extents,
+) -> Result<Vec<u8>, Error> {
+    let grid = block_grid(data.len(), block, full)?;
+    let cropped_len = compressed_size(block, size);
+    let block = block.extents();
+
+    let aligned = |origin: u32, size: u32, extent: u32, block: u32| {
+        origin % block == 0
+            && origin + size <= extent
+            && (size % block == 0 || origin + size == extent)
+    };
+    if !aligned(origin.x, size.x, full.x, block.x)
+        || !aligned(origin.y, size.y, full.y, block.y)
+        || !aligned(origin.z, size.z, full.z, block.z)
+    {
+        return Err(Error::BadParam);
+    }
+
+    let first = Extents::new_3d(origin.x / block.x, origin.y / block.y, origin.z / block.z);
+    let cropped = size.in_blocks(block);
+    let row = cropped.x as usize * Extents::block_byte_cost();
+
+    let mut out = Vec::with_capacity(cropped_len);
+    for z in first.z..first.z + cropped.z {
+        for y in first.y..first.y + cropped.y {
+            let start = ((z * grid.y + y) * grid.x + first.x) as usize * Extents::block_byte_cost();
+            out.extend_from_slice(&data[start..start + row]);
+        }
+    }
+
+    Ok(out)
+}
+
+/// Find the distinct blocks in compressed data, for example to measure how much of an atlas is
+/// made up of repeated solid or tiled regions. Returns the unique blocks in the order they first
+/// appear, and for every block in `data` the index of the matching unique block. A trailing
+/// partial block, if `data` isn't a multiple of `Extents::block_byte_cost()` long, is ignored.
+pub fn deduplicate_blocks(data: &[u8]) -> (Vec<[u8; 16]>, Vec<u32>) {
+    let mut unique = Vec::new();
+    let mut seen = HashMap::new();
+
+    let indices = data
+        .chunks_exact(Extents::block_byte_cost())
+        .map(|block| {
+            let mut bytes = [0; 16];
+            bytes.copy_from_slice(block);
+
+            *seen.entry(bytes).or_insert_with(|| {
+                unique.push(bytes);
+                unique.len() as u32 - 1
+            })
+        })
+        .collect();
+
+    (unique, indices)
+}
+
+/// Convert compressed image data from one block order to another. Use this to convert the output
+/// of `Context::compress_tiled_order` back to row-major order before uploading or decompressing
+/// it with other tools. Returns `Error::BadParam` if `data` isn't the length of an image with the
+/// given extents compressed with the given block size.
+pub fn reorder_blocks(
+    data: &[u8],
+    block: BlockSize,
+    extents: Extents,
+    from: BlockOrder,
+    to: BlockOrder,
+) -> Result<Vec<u8>, Error> {
+    let grid = block_grid(data.len(), block, extents)?;
+    let blocks = data
+        .chunks_exact(Extents::block_byte_cost())
+        .collect::<Vec<_>>();
+
+    let mut row_major = vec![&[][..]; blocks.len()];
+    for (position, index) in from.permutation(grid).into_iter().enumerate() {
+        row_major[index] = blocks[position];
+    }
+
+    Ok(to
+        .permutation(grid)
+        .into_iter()
+        .flat_map(|index| row_major[index].iter().copied())
+        .collect())
+}
+
+/// Decompresses an image one row of blocks at a time, so that the whole decoded image never has to
+/// be held in memory at once. Each item is the RGBA data for the texel rows covered by one row of
+/// blocks. For 3D images a row of blocks covers several layers, which are returned one after
+/// another.
+pub struct StreamDecompressor<'a, D> {
+    context: &'a mut Context,
+    data: &'a [u8],
+    extents: Extents,
+    swizzle: Swizzle,
+    row: u32,
+    _marker: PhantomData<D>,
+}
+
+impl<'a, D> StreamDecompressor<'a, D>
+where
+    D: DataType,
+{
+    /// Create a decompressor for `data`, which must be the complete compressed image with the
+    /// given extents, using the block size of `context`.
+    pub fn new(
+        context: &'a mut Context,
+        data: &'a [u8],
+        extents: Extents,
+        swizzle: Swizzle,
+    ) -> Self {
+        Self {
+            context,
+            data,
+            extents,
+            swizzle,
+            row: 0,
+            _marker: PhantomData,
+        }
+    }
+}
+
+impl<'a, D> Iterator for StreamDecompressor<'a, D>
+where
+    D: DataType + Copy,
+{
+    type Item = Result<Vec<D>, Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let block = self.context.config.block_size();
+        let grid = self.extents.in_blocks(block);
+        let rows = grid.y * grid.z;
+
+        if self.row >= rows {
+            return None;
+        }
+
+        let (block_y, block_z) = (self.row % grid.y, self.row / grid.y);
+        let row_bytes = grid.x as usize * Extents::block_byte_cost();
+        let start = self.row as usize * row_bytes;
+
+        let row_data = match self.data.get(start..start + row_bytes) {
+            Some(row_data) => row_data,
+            None => {
+                self.row = rows;
+                return Some(Err(Error::BadParam));
+            }
+        };
+
+        let extents = Extents::new_3d(
+            self.extents.x,
+            (self.extents.y - block_y * block.y).min(block.y),
+            (self.extents.z - block_z * block.z).min(block.z),
+        );
+
+        self.row += 1;
+
+        Some(
+            self.context
+                .decompress::<D>(row_data, extents, self.swizzle)
+                .map(|image| image.data.concat()),
+        )
+    }
+}
+
+/// The origin of the block at `index` in an image with the given extents, and the size of the part
+/// of it that lies inside the image.
+fn block_bounds(index: usize, block: Extents, extents: Extents) -> (Extents, Extents) {
+    let grid = extents.in_blocks(block);
+    let index = index as u32;
+
+    let origin = Extents::new_3d(
+        index % grid.x * block.x,
+        index / grid.x % grid.y * block.y,
+        index / (grid.x * grid.y) * block.z,
+    );
+    let size = Extents::new_3d(
+        block.x.min(extents.x - origin.x),
+        block.y.min(extents.y - origin.y),
+        block.z.min(extents.z - origin.z),
+    );
+
+    (origin, size)
+}
+
+/// A void-extent block, which decodes to the same UNORM16 color for every texel regardless of
+/// the block size.
+fn constant_block(rgba: [u16; 4]) -> [u8; 16] {
+    let mut block = [0; 16];
+    block[..8].copy_from_slice(&0xFFFF_FFFF_FFFF_FDFCu64.to_le_bytes());
+    for (bytes, component) in block[8..].chunks_exact_mut(2).zip(rgba) {
+        bytes.copy_from_slice(&component.to_le_bytes());
+    }
+    block
+}
+
+/// A void-extent block with an FP16 color, for the HDR profiles.
+fn constant_block_hdr(rgba: [half::f16; 4]) -> [u8; 16] {
+    let mut block = constant_block(rgba.map(half::f16::to_bits));
+    // Bit 9 of a void-extent block is set when its constant color is HDR.
+    block[1] |= 0x02;
+    block
+}
+
+/// Copy a box of compressed blocks from `src` (with block dimensions `src_grid`) into `dst` (with
+/// block dimensions `dst_grid`), placing the first block of `src` at block position `offset`.
+fn copy_blocks(dst: &mut [u8], dst_grid: Extents, src: &[u8], src_grid: Extents, offset: Extents) {
+    let row = src_grid.x as usize * Extents::block_byte_cost();
+
+    for z in 0..src_grid.z {
+        for y in 0..src_grid.y {
+            let src_start =
+                ((z * src_grid.y + y) * src_grid.x) as usize * Extents::block_byte_cost();
+            let dst_start = (((offset.z + z) * dst_grid.y + offset.y + y) * dst_grid.x + offset.x)
+                as usize
+                * Extents::block_byte_cost();
+
+            dst[dst_start..dst_start + row].copy_from_slice(&src[src_start..src_start + row]);
+        }
+    }
+}
+
+bitflags::bitflags! {
+    /// Configuration flags for the context.
+    #[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+    pub struct Flags: std::os::raw::c_uint {
+        /// Disable compression support.
+        const DECOMPRESS_ONLY  = astcenc_sys::ASTCENC_FLG_DECOMPRESS_ONLY;
+        /// Treat the image as a 2-component normal map for the purposes of error calculation.
+        /// Z will always be recalculated.
+        const MAP_NORMAL       = astcenc_sys::ASTCENC_FLG_MAP_NORMAL;
+        /// Weight any error in the RGB components by the A component, which leads to better
+        /// quality in areas with higher alpha by comparison.
+        const USE_ALPHA_WEIGHT = astcenc_sys::ASTCENC_FLG_USE_ALPHA_WEIGHT;
+        /// Calculate error using a perceptual algorithm instead of peak signal-to-noise ratio,
+        /// best used for normal maps. Not all input types support perceptual error calculation
+        /// at all.
+        const USE_PERCEPTUAL   = astcenc_sys::ASTCENC_FLG_USE_PERCEPTUAL;
+        /// Treat the image as RGBM-encoded HDR data, where the RGB must be multiplied by the
+        /// alpha and a fixed scale to get the HDR color. This stops the encoder from encoding a
+        /// multiplier of zero, which would lose the color. See `Context::compress_rgbm_auto`.
+        const MAP_RGBM         = astcenc_sys::ASTCENC_FLG_MAP_RGBM;
+    }
+}
+
+impl Flags {
+    fn into_sys(self) -> std::os::raw::c_uint {
+        self.bits()
+    }
+}
+
+impl Default for Flags {
+    fn default() -> Self {
+        Flags::USE_ALPHA_WEIGHT
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::convert::TryFrom;
+
+    #[test]
+    fn basic_works() {
+        let mut img = super::Image::<Vec<Vec<u8>>>::default();
+        let width = 1 + rand::random::<u32>() % 512;
+        let height = 1 + rand::random::<u32>() % 512;
+        let depth = 1 + rand::random::<u32>() % 4;
+        img.extents.x = width;
+        img.extents.y = height;
+        img.extents.z = depth;
+        img.data.resize_with(img.extents.z as usize, || {
+            (0..width * height * 4)
+                .map(|_| rand::random::<u8>())
+                .collect::<Vec<u8>>()
+        });
+
+        let mut ctx = super::Context::default();
+        let swz = super::Swizzle::rgba();
+
+        let data = ctx.compress(&img, swz).unwrap();
+
+        let img2 = ctx.decompress(&data, img.extents, swz).unwrap();
+
+        assert_eq!(img.extents, img2.extents);
+        assert_eq!(img.data.len(), img2.data.len());
+        assert!(img
+            .data
+            .iter()
+            .zip(img2.data.iter())
+            .all(|(a, b)| a.len() == b.len()));
+
+        // ASTC being a lossy compression algorithm, we can't compare the data between the two
+        // images, but we make sure that the two images are somewhat close.
+        assert!(
+            img.data
+                .iter()
                 .flatten()
                 .zip(img2.data.iter().flatten())
                 .map(|(px1, px2)| px1.abs_diff(*px2) as f32 / u8::MAX as f32)
@@ -703,4 +4638,2746 @@ mod tests {
                 < 0.5
         );
     }
+
+    #[test]
+    fn decompress_planar_matches_interleaved() {
+        let extents = super::Extents::new(32, 16);
+        let img = random_rgba8(extents);
+
+        let mut ctx = super::Context::default();
+        let swz = super::Swizzle::rgba();
+
+        let data = ctx.compress(&img, swz).unwrap();
+        let interleaved = ctx.decompress::<u8>(&data, extents, swz).unwrap();
+        let planes = ctx.decompress_planar::<u8>(&data, extents, swz).unwrap();
+
+        for plane in &planes {
+            assert_eq!(plane.len(), (extents.x * extents.y) as usize);
+        }
+        assert!(interleaved.data[0]
+            .chunks_exact(4)
+            .map(|texel| texel[0])
+            .eq(planes[0].iter().copied()));
+    }
+
+    #[test]
+    fn try_new_rejects_mismatched_length() {
+        let extents = super::Extents::new(8, 8);
+
+        assert!(super::Image::try_new(extents, vec![vec![0u8; 8 * 8 * 4]]).is_ok());
+        assert_eq!(
+            super::Image::try_new(extents, vec![vec![0u8; 8 * 8 * 3]]).err(),
+            Some(super::Error::BadParam)
+        );
+        assert_eq!(
+            super::Image::try_new(extents, vec![vec![0u8; 8 * 8 * 4]; 2]).err(),
+            Some(super::Error::BadParam)
+        );
+    }
+
+    /// A path in the temporary directory that no other test, or concurrent run of the tests, uses.
+    pub(crate) fn temp_path(name: &str) -> std::path::PathBuf {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+
+        static COUNTER: AtomicUsize = AtomicUsize::new(0);
+        std::env::temp_dir().join(format!(
+            "astcenc-rs-{}-{}-{}",
+            std::process::id(),
+            COUNTER.fetch_add(1, Ordering::Relaxed),
+            name
+        ))
+    }
+
+    /// An RGBA8 image of the given extents filled with random texels.
+    pub(crate) fn random_rgba8(extents: super::Extents) -> super::Image<Vec<Vec<u8>>> {
+        super::Image {
+            extents,
+            data: (0..extents.z)
+                .map(|_| {
+                    (0..extents.x * extents.y * 4)
+                        .map(|_| rand::random::<u8>())
+                        .collect::<Vec<u8>>()
+                })
+                .collect::<Vec<_>>(),
+        }
+    }
+
+    /// Peak signal-to-noise ratio over the `size`-texel square at `origin` of two 2D RGBA8 images.
+    fn region_psnr(a: &[u8], b: &[u8], width: u32, origin: (u32, u32), size: u32) -> f64 {
+        let mut sum = 0.0;
+        for y in origin.1..origin.1 + size {
+            let start = ((y * width + origin.0) * 4) as usize;
+            let end = start + (size * 4) as usize;
+            sum += a[start..end]
+                .iter()
+                .zip(&b[start..end])
+                .map(|(&a, &b)| (a as f64 - b as f64).powi(2))
+                .sum::<f64>();
+        }
+        let mse = sum / (size * size * 4) as f64;
+        10.0 * (255.0 * 255.0 / mse).log10()
+    }
+
+    #[test]
+    fn compress_regions_improves_region_quality() {
+        let extents = super::Extents::new(96, 96);
+        let data = (0..extents.y)
+            .flat_map(|y| {
+                (0..extents.x).flat_map(move |x| {
+                    let (x, y) = (x as f32, y as f32);
+                    [
+                        128.0 + 127.0 * (x * 0.7 + y * 0.3).sin(),
+                        128.0 + 127.0 * (x * 0.2 - y * 0.9).sin(),
+                        128.0 + 127.0 * ((x + y) * 0.45).cos(),
+                        255.0,
+                    ]
+                    .map(|c| c as u8)
+                })
+            })
+            .collect::<Vec<u8>>();
+        let img = super::Image {
+            extents,
+            data: vec![data],
+        };
+
+        let mut ctx = super::Context::new(
+            super::ConfigBuilder::new()
+                .with_preset(super::PRESET_FASTEST)
+                .with_block_size(super::BlockSize::new_2d(8, 8).unwrap())
+                .build()
+                .unwrap(),
+        )
+        .unwrap();
+        let swz = super::Swizzle::rgba();
+
+        let region = (
+            super::Extents::new(32, 32),
+            super::Extents::new(32, 32),
+            super::PRESET_EXHAUSTIVE,
+        );
+        let compressed = ctx.compress_regions(&img, swz, &[region]).unwrap();
+        let plain = ctx.compress(&img, swz).unwrap();
+        assert_eq!(plain.len(), compressed.len());
+        assert_ne!(plain, compressed);
+
+        let decoded = ctx.decompress::<u8>(&compressed, extents, swz).unwrap();
+
+        let center = region_psnr(&img.data[0], &decoded.data[0], extents.x, (32, 32), 32);
+        let corner = region_psnr(&img.data[0], &decoded.data[0], extents.x, (0, 0), 32);
+        assert!(center > corner, "center {} <= corner {}", center, corner);
+    }
+
+    #[test]
+    fn validate_detects_corruption() {
+        let extents = super::Extents::new(64, 48);
+        let img = random_rgba8(extents);
+
+        let mut ctx = super::Context::default();
+        let mut data = ctx.compress(&img, super::Swizzle::rgba()).unwrap();
+
+        let block = super::BlockSize::default();
+        let profile = super::Profile::LdrRgba;
+
+        assert_eq!(ctx.validate(&data, block, extents, profile), Ok(()));
+        assert_eq!(
+            ctx.validate(&data, block, extents, super::Profile::HdrRgba),
+            Ok(())
+        );
+        assert_eq!(
+            ctx.validate(&data[..data.len() - 16], block, extents, profile),
+            Err(super::Error::BadParam)
+        );
+
+        // A block mode with the low four bits cleared is reserved.
+        data[16..32].fill(0);
+        assert_eq!(
+            ctx.validate(&data, block, extents, profile),
+            Err(super::Error::BadData)
+        );
+    }
+
+    #[test]
+    fn optimize_for_sets_flags_and_weights() {
+        let normal = super::ConfigBuilder::new()
+            .with_optimize_for(super::TextureKind::NormalMap)
+            .build()
+            .unwrap();
+        assert!(
+            super::Flags::from_bits_truncate(normal.inner.flags).contains(super::Flags::MAP_NORMAL)
+        );
+
+        let mask = super::ConfigBuilder::new()
+            .with_optimize_for(super::TextureKind::Mask)
+            .build()
+            .unwrap();
+        assert!(!super::Flags::from_bits_truncate(mask.inner.flags)
+            .contains(super::Flags::USE_ALPHA_WEIGHT));
+        assert!(mask.inner.cw_a_weight > mask.inner.cw_r_weight);
+        assert!(mask.inner.cw_a_weight > mask.inner.cw_g_weight);
+        assert!(mask.inner.cw_a_weight > mask.inner.cw_b_weight);
+    }
+
+    #[test]
+    fn block_byte_cost_is_16() {
+        assert_eq!(super::Extents::block_byte_cost(), 16);
+    }
+
+    #[test]
+    fn compress_next_mip_halves_extents() {
+        let extents = super::Extents::new(64, 64);
+        let img = random_rgba8(extents);
+
+        let mut ctx = super::Context::default();
+        let block = super::BlockSize::default();
+        let swz = super::Swizzle::rgb1();
+        let base = ctx.compress(&img, swz).unwrap();
+
+        let (level1, extents1) = ctx
+            .compress_next_mip::<u8>(&base, extents, block, swz)
+            .unwrap();
+        assert_eq!(extents1, super::Extents::new(32, 32));
+        assert_eq!(level1.len(), 8 * 8 * 16);
+
+        let (level2, extents2) = ctx
+            .compress_next_mip::<u8>(&level1, extents1, block, swz)
+            .unwrap();
+        assert_eq!(extents2, super::Extents::new(16, 16));
+        assert_eq!(level2.len(), 4 * 4 * 16);
+
+        let decoded = ctx
+            .decompress::<u8>(&level2, extents2, super::Swizzle::rgba())
+            .unwrap();
+        assert!(decoded.data[0].chunks_exact(4).all(|texel| texel[3] == 255));
+
+        assert_eq!(
+            ctx.compress_next_mip::<u8>(
+                &level1,
+                extents1,
+                super::BlockSize::new_2d(6, 6).unwrap(),
+                swz
+            ),
+            Err(super::Error::BadBlockSize)
+        );
+    }
+
+    #[test]
+    fn requires_float_input() {
+        assert!(super::Profile::HdrRgba.requires_float_input());
+        assert!(super::Profile::HdrRgbLdrA.requires_float_input());
+        assert!(!super::Profile::LdrRgba.requires_float_input());
+        assert!(!super::Profile::LdrSrgb.requires_float_input());
+    }
+
+    #[test]
+    fn stream_decompressor_matches_full_decode() {
+        let extents = super::Extents::new(32, 64);
+        let img = random_rgba8(extents);
+
+        let mut ctx = super::Context::default();
+        let swz = super::Swizzle::rgba();
+        let data = ctx.compress(&img, swz).unwrap();
+        let full = ctx.decompress::<u8>(&data, extents, swz).unwrap();
+
+        let rows = super::StreamDecompressor::<u8>::new(&mut ctx, &data, extents, swz)
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+
+        assert_eq!(rows.len(), 16);
+        assert!(rows.iter().all(|row| row.len() == 32 * 4 * 4));
+        assert_eq!(rows.concat(), full.data[0]);
+    }
+
+    #[test]
+    fn decompress_normal_reconstructs_z() {
+        let extents = super::Extents::new(32, 32);
+        let data = (0..extents.y)
+            .flat_map(|y| {
+                (0..extents.x).flat_map(move |x| {
+                    let nx = (x as f32 / 16.0 - 1.0) * 0.6;
+                    let ny = (y as f32 / 16.0 - 1.0) * 0.6;
+                    [nx, ny, 0.0, 0.0].map(|n| ((n * 0.5 + 0.5) * 255.0).round() as u8)
+                })
+            })
+            .collect::<Vec<u8>>();
+        let img = super::Image {
+            extents,
+            data: vec![data],
+        };
+
+        let mut ctx = super::Context::new(
+            super::ConfigBuilder::new()
+                .with_optimize_for(super::TextureKind::NormalMap)
+                .build()
+                .unwrap(),
+        )
+        .unwrap();
+
+        let compressed = ctx.compress(&img, super::Swizzle::rrrg()).unwrap();
+        let normals = ctx.decompress_normal::<u8>(&compressed, extents).unwrap();
+
+        let mean_error = normals.data[0]
+            .chunks_exact(4)
+            .map(|texel| {
+                let [x, y, z] = [texel[0], texel[1], texel[2]].map(|c| c as f32 / 127.5 - 1.0);
+                assert!(z >= -0.05);
+                ((x * x + y * y + z * z).sqrt() - 1.0).abs()
+            })
+            .sum::<f32>()
+            / (extents.x * extents.y) as f32;
+
+        assert!(mean_error < 0.05, "mean normal length error {}", mean_error);
+    }
+
+    #[test]
+    fn try_default_succeeds() {
+        assert!(super::Context::try_default().is_ok());
+    }
+
+    #[test]
+    fn block_grid_round_trips() {
+        let block = super::BlockSize::default();
+        let image = super::Extents::new(256, 256);
+
+        let grid = super::block_grid(64 * 64 * 16, block, image).unwrap();
+        assert_eq!(grid, super::Extents::new_3d(64, 64, 1));
+        assert_eq!(super::image_from_block_grid(grid, block), image);
+
+        // A zero block size can't be passed at all, so it can't divide by zero.
+        assert_eq!(
+            super::BlockSize::try_from(super::Extents::default()),
+            Err(super::Error::BadBlockSize)
+        );
+
+        assert_eq!(
+            super::block_grid(64 * 64 * 16, super::BlockSize::new_2d(8, 8).unwrap(), image),
+            Err(super::Error::BadParam)
+        );
+    }
+
+    #[test]
+    fn compress_premultiplied_uses_alpha_weight() {
+        let extents = super::Extents::new(32, 32);
+        let img = random_rgba8(extents);
+        let premultiplied = super::Image {
+            extents,
+            data: vec![img.data[0]
+                .chunks_exact(4)
+                .flat_map(|texel| {
+                    let alpha = texel[3] as f32 / 255.0;
+                    [
+                        (texel[0] as f32 * alpha).round() as u8,
+                        (texel[1] as f32 * alpha).round() as u8,
+                        (texel[2] as f32 * alpha).round() as u8,
+                        texel[3],
+                    ]
+                })
+                .collect::<Vec<u8>>()],
+        };
+        let swz = super::Swizzle::rgba();
+
+        let mut unweighted = super::Context::new(
+            super::ConfigBuilder::new()
+                .with_flags(super::Flags::empty())
+                .build()
+                .unwrap(),
+        )
+        .unwrap();
+        let mut weighted = super::Context::default();
+
+        assert_eq!(
+            unweighted.compress_premultiplied(&img, swz).unwrap(),
+            weighted.compress(&premultiplied, swz).unwrap()
+        );
+    }
+
+    #[test]
+    fn decoded_range() {
+        use super::{Profile, Type};
+
+        assert_eq!(
+            super::decoded_range(Type::U8, Profile::LdrRgba),
+            (0.0, 255.0)
+        );
+        assert_eq!(
+            super::decoded_range(Type::U8, Profile::HdrRgba),
+            (0.0, 255.0)
+        );
+        assert_eq!(
+            super::decoded_range(Type::F32, Profile::LdrRgba),
+            (0.0, 1.0)
+        );
+        assert_eq!(
+            super::decoded_range(Type::F16, Profile::LdrSrgb),
+            (0.0, 1.0)
+        );
+        assert_eq!(
+            super::decoded_range(Type::F32, Profile::HdrRgba),
+            (f64::MIN, f64::MAX)
+        );
+        assert_eq!(
+            super::decoded_range(Type::F16, Profile::HdrRgbLdrA),
+            (f64::MIN, f64::MAX)
+        );
+    }
+
+    #[test]
+    fn context_usable_after_reinit() {
+        let extents = super::Extents::new(24, 24);
+        let img = random_rgba8(extents);
+
+        let mut ctx = super::Context::default();
+        let swz = super::Swizzle::rgba();
+        let before = ctx.compress(&img, swz).unwrap();
+
+        // This is what `reset` falls back to when the library fails to reset the context.
+        ctx.reinit().unwrap();
+
+        assert_eq!(ctx.compress(&img, swz).unwrap(), before);
+        assert!(ctx.decompress::<u8>(&before, extents, swz).is_ok());
+    }
+
+    #[test]
+    fn decoded_size_bytes() {
+        let uhd = super::Extents::new(3840, 2160);
+        assert_eq!(
+            super::decoded_size_bytes(uhd, super::Type::U8),
+            3840 * 2160 * 4
+        );
+        assert_eq!(
+            super::decoded_size_bytes(uhd, super::Type::F32),
+            3840 * 2160 * 4 * 4
+        );
+
+        let huge = super::Extents::new_3d(65536, 65536, 16);
+        assert_eq!(
+            super::decoded_size_bytes(huge, super::Type::F16),
+            65536 * 65536 * 16 * 4 * 2
+        );
+    }
+
+    #[test]
+    fn compress_la_round_trips() {
+        let extents = super::Extents::new(32, 32);
+        let la = (0..extents.y)
+            .flat_map(|y| (0..extents.x).flat_map(move |x| [(x * 8) as u8, (y * 8) as u8]))
+            .collect::<Vec<u8>>();
+
+        let mut ctx = super::Context::default();
+        let swz = super::Swizzle {
+            r: super::Selector::Red,
+            g: super::Selector::Alpha,
+            b: super::Selector::Zero,
+            a: super::Selector::One,
+        };
+        let (data, decode_swz) = ctx.compress_la(&la, extents, swz).unwrap();
+        assert_eq!(decode_swz, super::Swizzle::rrrg());
+        let decoded = ctx.decompress::<u8>(&data, extents, decode_swz).unwrap();
+
+        for (texel, expected) in decoded.data[0].chunks_exact(4).zip(la.chunks_exact(2)) {
+            for &luma in &texel[..3] {
+                assert!(luma.abs_diff(expected[0]) <= 8);
+            }
+            assert!(texel[3].abs_diff(expected[1]) <= 8);
+        }
+
+        assert_eq!(
+            ctx.compress_la(&la[1..], extents, swz).err(),
+            Some(super::Error::BadParam)
+        );
+    }
+
+    #[test]
+    fn compress_is_deterministic() {
+        let extents = super::Extents::new(64, 64);
+        let img = random_rgba8(extents);
+        let swz = super::Swizzle::rgba();
+
+        let first = super::Context::default().compress(&img, swz).unwrap();
+
+        let mut ctx = super::Context::default();
+        assert_eq!(ctx.compress(&img, swz).unwrap(), first);
+        assert_eq!(ctx.compress(&img, swz).unwrap(), first);
+    }
+
+    #[test]
+    fn from_reader_reads_rgba() {
+        let extents = super::Extents::new(16, 8);
+        let bytes = (0..extents.x * extents.y * 4)
+            .map(|_| rand::random::<u8>())
+            .collect::<Vec<u8>>();
+
+        let img = super::Image::<Vec<Vec<u8>>>::from_reader(
+            std::io::Cursor::new(&bytes),
+            extents,
+            super::Type::U8,
+        )
+        .unwrap();
+        assert_eq!(img.data, [bytes.clone()]);
+
+        let mut ctx = super::Context::default();
+        assert!(ctx.compress(&img, super::Swizzle::rgba()).is_ok());
+
+        let floats = [0.25f32, 0.5, 0.75, 1.0];
+        let float_bytes = floats
+            .iter()
+            .flat_map(|f| f.to_ne_bytes())
+            .collect::<Vec<u8>>();
+        let img = super::Image::<Vec<Vec<f32>>>::from_reader(
+            &float_bytes[..],
+            super::Extents::new(1, 1),
+            super::Type::F32,
+        )
+        .unwrap();
+        assert_eq!(img.data, [floats.to_vec()]);
+
+        assert_eq!(
+            super::Image::<Vec<Vec<u8>>>::from_reader(&bytes[1..], extents, super::Type::U8).err(),
+            Some(super::Error::BadParam)
+        );
+        assert_eq!(
+            super::Image::<Vec<Vec<u8>>>::from_reader(&bytes[..], extents, super::Type::F32).err(),
+            Some(super::Error::BadParam)
+        );
+    }
+
+    #[test]
+    fn tradeoff_score_ranks_configs() {
+        let best = super::ConfigBuilder::new()
+            .with_block_size(super::BlockSize::new_2d(4, 4).unwrap())
+            .with_preset(super::PRESET_EXHAUSTIVE)
+            .build()
+            .unwrap();
+        let worst = super::ConfigBuilder::new()
+            .with_block_size(super::BlockSize::new_2d(12, 12).unwrap())
+            .with_preset(super::PRESET_FASTEST)
+            .build()
+            .unwrap();
+
+        assert!(best.tradeoff_score() > worst.tradeoff_score());
+        assert!(worst.tradeoff_score() > 0.0);
+    }
+
+    #[test]
+    fn decode_iter_matches_bulk_decode() {
+        let extents = super::Extents::new(30, 18);
+        let img = random_rgba8(extents);
+
+        let mut ctx = super::Context::default();
+        let swz = super::Swizzle::rgba();
+        let data = ctx.compress(&img, swz).unwrap();
+        let bulk = ctx.decompress::<u8>(&data, extents, swz).unwrap();
+
+        let mut reconstructed = vec![0u8; bulk.data[0].len()];
+        let mut blocks = 0;
+        for (origin, texels) in ctx.decode_iter::<u8>(&data, ctx.block_size(), extents, swz) {
+            let width = (extents.x - origin.x).min(4) as usize * 4;
+            for (row, texels) in texels.chunks_exact(width).enumerate() {
+                let start =
+                    ((origin.y as usize + row) * extents.x as usize + origin.x as usize) * 4;
+                reconstructed[start..start + width].copy_from_slice(texels);
+            }
+            blocks += 1;
+        }
+
+        assert_eq!(blocks, 8 * 5);
+        assert_eq!(reconstructed, bulk.data[0]);
+    }
+
+    #[test]
+    fn alpha_cutoff_preserves_alpha_near_cutoff() {
+        let extents = super::Extents::new(64, 64);
+        let img = super::Image {
+            extents,
+            data: vec![(0..extents.x * extents.y)
+                .flat_map(|_| {
+                    [
+                        rand::random::<u8>(),
+                        rand::random::<u8>(),
+                        rand::random::<u8>(),
+                        rand::random::<u8>(),
+                    ]
+                })
+                .collect::<Vec<u8>>()],
+        };
+        let swz = super::Swizzle::rgba();
+
+        let alpha_error = |builder: super::ConfigBuilder| {
+            let mut ctx = super::Context::new(builder.build().unwrap()).unwrap();
+            let data = ctx.compress(&img, swz).unwrap();
+            let decoded = ctx.decompress::<u8>(&data, extents, swz).unwrap();
+            // Only texels whose alpha is near the cutoff can end up on the wrong side of it.
+            img.data[0]
+                .chunks_exact(4)
+                .zip(decoded.data[0].chunks_exact(4))
+                .filter(|(a, _)| a[3].abs_diff(128) <= 32)
+                .map(|(a, b)| a[3].abs_diff(b[3]) as f64)
+                .sum::<f64>()
+        };
+
+        let plain = alpha_error(super::ConfigBuilder::new());
+        let cutout = alpha_error(super::ConfigBuilder::new().with_alpha_cutoff(0.5));
+        assert!(cutout < plain, "cutout {} >= plain {}", cutout, plain);
+
+        assert_eq!(
+            super::ConfigBuilder::new()
+                .with_alpha_cutoff(1.5)
+                .build()
+                .err(),
+            Some(super::Error::BadParam)
+        );
+    }
+
+    #[test]
+    fn compress_jobs_uses_per_job_block_sizes() {
+        let extents = super::Extents::new(32, 32);
+        let job = |block| super::CompressJob {
+            config: super::ConfigBuilder::new().with_block_size(block),
+            image: random_rgba8(extents),
+            swizzle: super::Swizzle::rgba(),
+        };
+
+        let small = super::BlockSize::new_2d(4, 4).unwrap();
+        let large = super::BlockSize::new_2d(8, 8).unwrap();
+        let jobs = [job(small), job(large), job(small)];
+
+        let results = super::compress_jobs(&jobs)
+            .into_iter()
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+
+        assert_eq!(results.len(), 3);
+        for (result, (block, blocks)) in
+            results
+                .iter()
+                .zip([(small, 8 * 8), (large, 4 * 4), (small, 8 * 8)])
+        {
+            assert_eq!(result.block, block);
+            assert_eq!(result.extents, extents);
+            assert_eq!(result.data.len(), blocks * 16);
+        }
+    }
+
+    #[test]
+    fn guess_profile_detects_ldr_and_hdr() {
+        let extents = super::Extents::new(16, 16);
+        let block = super::BlockSize::default();
+        let swz = super::Swizzle::rgba();
+
+        let ldr = random_rgba8(extents);
+        let data = super::Context::default().compress(&ldr, swz).unwrap();
+        assert_eq!(
+            super::guess_profile(&data, block, extents),
+            Some(super::Profile::LdrRgba)
+        );
+        assert_eq!(super::guess_profile(&data[16..], block, extents), None);
+
+        let hdr = super::Image {
+            extents,
+            data: vec![(0..extents.x * extents.y * 4)
+                .map(|i| {
+                    if i % 4 == 3 {
+                        1.0
+                    } else {
+                        4.0 + (i % 7) as f32
+                    }
+                })
+                .collect::<Vec<f32>>()],
+        };
+        let data = super::Context::new(
+            super::ConfigBuilder::new()
+                .with_profile(super::Profile::HdrRgba)
+                .build()
+                .unwrap(),
+        )
+        .unwrap()
+        .compress(&hdr, swz)
+        .unwrap();
+        assert!(super::guess_profile(&data, block, extents)
+            .unwrap()
+            .requires_float_input());
+    }
+
+    #[test]
+    fn format_tag_round_trips() {
+        let tags = [
+            super::FormatTag {
+                block: super::BlockSize::default(),
+                profile: super::Profile::LdrRgba,
+                swizzle: super::Swizzle::rgba(),
+            },
+            super::FormatTag {
+                block: super::BlockSize::new_2d(12, 10).unwrap(),
+                profile: super::Profile::LdrSrgb,
+                swizzle: super::Swizzle::rgb1(),
+            },
+            super::FormatTag {
+                block: super::BlockSize::new_2d(6, 6).unwrap(),
+                profile: super::Profile::LdrRgba,
+                swizzle: super::Swizzle::raz1(),
+            },
+            super::FormatTag {
+                block: super::BlockSize::new_3d(6, 5, 5).unwrap(),
+                profile: super::Profile::HdrRgbLdrA,
+                swizzle: super::Swizzle::rrrg(),
+            },
+            super::FormatTag {
+                block: super::BlockSize::new_2d(8, 8).unwrap(),
+                profile: super::Profile::HdrRgba,
+                swizzle: super::Swizzle::rrr1(),
+            },
+        ];
+
+        for tag in tags {
+            let packed = tag.to_u32();
+            assert_eq!(packed >> 26, 0);
+            assert_eq!(super::FormatTag::from_u32(packed), Ok(tag));
+        }
+
+        assert_eq!(
+            super::FormatTag::from_u32(1 << 31),
+            Err(super::Error::BadData)
+        );
+        assert_eq!(super::FormatTag::from_u32(0), Err(super::Error::BadData));
+
+        // A 7x7 block fits in the fields, but isn't an ASTC block size.
+        assert_eq!(
+            super::FormatTag::from_u32(7 | 7 << 4 | 1 << 8),
+            Err(super::Error::BadData)
+        );
+    }
+
+    #[test]
+    fn refinement_iterations() {
+        let extents = super::Extents::new(64, 64);
+        let img = random_rgba8(extents);
+        let swz = super::Swizzle::rgba();
+
+        let compress = |builder: super::ConfigBuilder| {
+            let config = builder.with_preset(super::PRESET_FASTEST).build().unwrap();
+            let iterations = config.inner.tune_refinement_limit;
+            let data = super::Context::new(config)
+                .unwrap()
+                .compress(&img, swz)
+                .unwrap();
+            (iterations, data)
+        };
+
+        let (default_iterations, default_data) = compress(super::ConfigBuilder::new());
+        let (iterations, data) =
+            compress(super::ConfigBuilder::new().with_refinement_iterations(16));
+
+        assert_eq!(iterations, 16);
+        assert_ne!(default_iterations, 16);
+        assert_eq!(data.len(), default_data.len());
+        assert_ne!(data, default_data);
+    }
+
+    #[test]
+    fn resize() {
+        let extents = super::Extents::new(128, 128);
+        let img = super::Image {
+            extents,
+            data: vec![(0..extents.y)
+                .flat_map(|y| {
+                    (0..extents.x).flat_map(move |x| [x as u8 * 2, y as u8 * 2, 128, 255])
+                })
+                .collect::<Vec<u8>>()],
+        };
+
+        let small = img.resize(super::Extents::new(64, 64)).unwrap();
+        assert_eq!(small.extents, super::Extents::new(64, 64));
+        assert_eq!(small.data.len(), 1);
+        assert_eq!(small.data[0].len(), 64 * 64 * 4);
+
+        let center = ((32 * 64 + 32) * 4) as usize;
+        let texel = &small.data[0][center..center + 4];
+        assert!(texel[0].abs_diff(129) <= 2, "{:?}", texel);
+        assert!(texel[1].abs_diff(129) <= 2, "{:?}", texel);
+        assert_eq!(&texel[2..], &[128, 255]);
+
+        let hdr = super::Image {
+            extents: super::Extents::new(4, 4),
+            data: vec![vec![4.0f32; 4 * 4 * 4]],
+        };
+        let large = hdr.resize(super::Extents::new(6, 10)).unwrap();
+        assert_eq!(large.data[0].len(), 6 * 10 * 4);
+        assert!(large.data[0].iter().all(|&c| (c - 4.0).abs() < 1e-5));
+
+        assert_eq!(
+            img.resize(super::Extents::new_3d(64, 64, 2)).err(),
+            Some(super::Error::BadParam)
+        );
+    }
+
+    #[test]
+    fn extents_le_bytes() {
+        for extents in [
+            super::Extents::default(),
+            super::Extents::default_block_size(),
+            super::Extents::new(1920, 1080),
+            super::Extents::new_3d(6, 5, 5),
+            super::Extents::new_3d(u32::MAX, 0x0102_0304, 1),
+        ] {
+            assert_eq!(
+                super::Extents::from_le_bytes(&extents.to_le_bytes()),
+                extents
+            );
+        }
+
+        assert_eq!(
+            super::Extents::new_3d(0x0102_0304, 2, 3).to_le_bytes(),
+            [4, 3, 2, 1, 2, 0, 0, 0, 3, 0, 0, 0]
+        );
+    }
+
+    #[test]
+    fn compress_atlas() {
+        let size = super::Extents::new(16, 16);
+        let image = |texel: fn(u32, u32) -> [u8; 4]| super::Image {
+            extents: size,
+            data: vec![(0..size.y)
+                .flat_map(|y| (0..size.x).flat_map(move |x| texel(x, y)))
+                .collect::<Vec<u8>>()],
+        };
+
+        let normal = image(|x, y| [(x * 16) as u8, (y * 16) as u8, 0, 255]);
+        let albedo = image(|x, y| [255 - (x * 16) as u8, (y * 8) as u8, 64, 255]);
+
+        let extents = super::Extents::new(32, 20);
+        let mut ctx = super::Context::default();
+        let data = ctx
+            .compress_atlas(
+                extents,
+                &[
+                    (normal, super::Swizzle::rrrg()),
+                    (albedo, super::Swizzle::rgba()),
+                ],
+                &[
+                    super::Extents::new_3d(0, 0, 0),
+                    super::Extents::new_3d(16, 0, 0),
+                ],
+            )
+            .unwrap();
+        assert_eq!(
+            data.len(),
+            super::compressed_size(ctx.block_size(), extents)
+        );
+
+        let decoded = ctx
+            .decompress::<u8>(&data, extents, super::Swizzle::rgba())
+            .unwrap();
+        let texel = |x: u32, y: u32| {
+            let i = ((y * extents.x + x) * 4) as usize;
+            &decoded.data[0][i..i + 4]
+        };
+
+        let mut error = 0;
+        for y in 0..16 {
+            for x in 0..16 {
+                let expected_normal = [
+                    (x * 16) as u8,
+                    (x * 16) as u8,
+                    (x * 16) as u8,
+                    (y * 16) as u8,
+                ];
+                let expected_albedo = [255 - (x * 16) as u8, (y * 8) as u8, 64, 255];
+                for (a, b) in texel(x, y).iter().zip(expected_normal) {
+                    error += a.abs_diff(b) as u32;
+                }
+                for (a, b) in texel(x + 16, y).iter().zip(expected_albedo) {
+                    error += a.abs_diff(b) as u32;
+                }
+            }
+        }
+        assert!(error < 16 * 16 * 8 * 4, "error {}", error);
+
+        assert_eq!(texel(5, 18), &[0, 0, 0, 0]);
+
+        let misaligned = ctx.compress_atlas(
+            extents,
+            &[(image(|_, _| [0; 4]), super::Swizzle::rgba())],
+            &[super::Extents::new_3d(2, 0, 0)],
+        );
+        assert_eq!(misaligned, Err(super::Error::BadParam));
+    }
+
+    #[test]
+    fn compress_auto_opaque() {
+        let extents = super::Extents::new(32, 32);
+        // The alpha is stored in the blue channel, which is 255 everywhere, so the auto path
+        // swaps it for a constant instead of encoding it.
+        let img = super::Image {
+            extents,
+            data: vec![(0..extents.x * extents.y)
+                .flat_map(|_| [rand::random::<u8>(), rand::random::<u8>(), 255, 0])
+                .collect::<Vec<u8>>()],
+        };
+        let swz = super::Swizzle {
+            a: super::Selector::Blue,
+            ..super::Swizzle::rgba()
+        };
+        let mut ctx = super::Context::default();
+
+        let plain = ctx.compress(&img, swz).unwrap();
+        let plain = ctx
+            .decompress::<u8>(&plain, extents, super::Swizzle::rgba())
+            .unwrap();
+
+        let (auto, decode_swz) = ctx.compress_auto_opaque(&img, swz).unwrap();
+        assert_eq!(decode_swz, super::Swizzle::rgb1());
+        let auto = ctx.decompress::<u8>(&auto, extents, decode_swz).unwrap();
+        assert!(auto.data[0].chunks_exact(4).all(|texel| texel[3] == 255));
+
+        let rgb_psnr = |decoded: &[u8]| {
+            let mse = img.data[0]
+                .chunks_exact(4)
+                .zip(decoded.chunks_exact(4))
+                .flat_map(|(a, b)| a[..3].iter().zip(&b[..3]))
+                .map(|(&a, &b)| (a as f64 - b as f64).powi(2))
+                .sum::<f64>()
+                / (extents.x * extents.y * 3) as f64;
+            10.0 * (255.0 * 255.0 / mse).log10()
+        };
+        let plain_psnr = rgb_psnr(&plain.data[0]);
+        let auto_psnr = rgb_psnr(&auto.data[0]);
+        assert!(auto_psnr >= plain_psnr, "{} < {}", auto_psnr, plain_psnr);
+
+        let mut translucent = img;
+        translucent.data[0][2] = 254;
+        let (_, decode_swz) = ctx.compress_auto_opaque(&translucent, swz).unwrap();
+        assert_eq!(decode_swz, super::Swizzle::rgba());
+    }
+
+    #[test]
+    fn compress_opaque() {
+        let extents = super::Extents::new(24, 16);
+        let img = random_rgba8(extents);
+        let swz = super::Swizzle::rgba();
+        let mut ctx = super::Context::default();
+
+        let data = ctx.compress_opaque(&img, swz).unwrap();
+        let decoded = ctx.decompress::<u8>(&data, extents, swz).unwrap();
+        assert!(decoded.data[0].chunks_exact(4).all(|texel| texel[3] == 255));
+    }
+
+    #[test]
+    fn split_channels() {
+        let extents = super::Extents::new(7, 5);
+        let n = (extents.x * extents.y) as usize;
+        let img = super::Image {
+            extents,
+            data: vec![(0..n * 4).map(|i| i as u8).collect::<Vec<u8>>()],
+        };
+
+        let (r, rg, rgba) = img.split_channels();
+        assert_eq!((r.len(), rg.len(), rgba.len()), (n, 2 * n, 4 * n));
+
+        for i in 0..n {
+            assert_eq!(r[i], (i * 4) as u8);
+            assert_eq!(&rg[i * 2..i * 2 + 2], &[(i * 4) as u8, (i * 4 + 1) as u8]);
+        }
+        assert_eq!(rgba, img.data[0]);
+    }
+
+    #[test]
+    fn decompress_linear() {
+        let extents = super::Extents::new(8, 8);
+        let img = super::Image {
+            extents,
+            data: vec![(0..extents.x * extents.y)
+                .flat_map(|_| [128u8, 128, 128, 128])
+                .collect::<Vec<u8>>()],
+        };
+        let swz = super::Swizzle::rgba();
+
+        let mut ctx = super::Context::new(
+            super::ConfigBuilder::new()
+                .with_profile(super::Profile::LdrSrgb)
+                .build()
+                .unwrap(),
+        )
+        .unwrap();
+        let data = ctx.compress(&img, swz).unwrap();
+        let decoded = ctx.decompress_linear(&data, extents, swz).unwrap();
+
+        let srgb = 128.0f32 / 255.0;
+        let linear = ((srgb + 0.055) / 1.055).powf(2.4);
+        for texel in decoded.data[0].chunks_exact(4) {
+            for &component in &texel[..3] {
+                assert!((component - linear).abs() < 0.01, "{:?}", texel);
+            }
+            assert!((texel[3] - srgb).abs() < 0.01, "{:?}", texel);
+        }
+
+        let mut ctx = super::Context::default();
+        let data = ctx.compress(&img, swz).unwrap();
+        let decoded = ctx.decompress_linear(&data, extents, swz).unwrap();
+        assert!((decoded.data[0][0] - srgb).abs() < 0.01);
+    }
+
+    #[test]
+    fn compress_with_exact() {
+        let extents = super::Extents::new_3d(16, 12, 2);
+        let img = random_rgba8(extents);
+        let swz = super::Swizzle::rgba();
+        let mut ctx = super::Context::default();
+
+        let coords = [
+            super::Extents::new_3d(0, 0, 0),
+            super::Extents::new_3d(15, 11, 1),
+            super::Extents::new_3d(3, 7, 1),
+        ];
+        let (data, exact) = ctx.compress_with_exact(&img, swz, &coords).unwrap();
+        assert_eq!(data, ctx.compress(&img, swz).unwrap());
+        assert_eq!(exact.len(), coords.len());
+
+        for (&coord, (exact_coord, texel)) in coords.iter().zip(exact) {
+            assert_eq!(coord, exact_coord);
+            let i = ((coord.y * extents.x + coord.x) * 4) as usize;
+            assert_eq!(&texel[..], &img.data[coord.z as usize][i..i + 4]);
+        }
+
+        assert_eq!(
+            ctx.compress_with_exact(&img, swz, &[super::Extents::new_3d(16, 0, 0)]),
+            Err(super::Error::BadParam)
+        );
+    }
+
+    #[test]
+    fn compress_tiled_order() {
+        let extents = super::Extents::new(40, 28);
+        let img = random_rgba8(extents);
+        let swz = super::Swizzle::rgba();
+        let block = super::BlockSize::default();
+        let mut ctx = super::Context::default();
+
+        let row_major = ctx.compress(&img, swz).unwrap();
+        let morton = ctx
+            .compress_tiled_order(&img, swz, super::BlockOrder::Morton)
+            .unwrap();
+        assert_eq!(morton.len(), row_major.len());
+        assert_ne!(morton, row_major);
+
+        // The first blocks in Morton order are the top-left 2x2 square.
+        assert_eq!(morton[16..32], row_major[16..32]);
+        assert_eq!(morton[32..48], row_major[10 * 16..11 * 16]);
+        assert_eq!(morton[48..64], row_major[11 * 16..12 * 16]);
+
+        let restored = super::reorder_blocks(
+            &morton,
+            block,
+            extents,
+            super::BlockOrder::Morton,
+            super::BlockOrder::RowMajor,
+        )
+        .unwrap();
+        assert_eq!(restored, row_major);
+
+        let standard = ctx.decompress::<u8>(&row_major, extents, swz).unwrap();
+        let tiled = ctx
+            .decompress_tiled_order::<u8>(&morton, extents, swz, super::BlockOrder::Morton)
+            .unwrap();
+        assert_eq!(tiled.data, standard.data);
+    }
+
+    #[test]
+    fn estimate_quality() {
+        let complexity = 0.1;
+        let small = super::estimate_quality(super::BlockSize::new_2d(4, 4).unwrap(), complexity);
+        let medium = super::estimate_quality(super::BlockSize::new_2d(8, 8).unwrap(), complexity);
+        let large = super::estimate_quality(super::BlockSize::new_2d(12, 12).unwrap(), complexity);
+        assert!(
+            small > medium && medium > large,
+            "{} {} {}",
+            small,
+            medium,
+            large
+        );
+
+        let detailed = super::estimate_quality(super::BlockSize::new_2d(4, 4).unwrap(), 0.5);
+        assert!(detailed < small);
+
+        let flat = super::estimate_quality(super::BlockSize::new_2d(12, 12).unwrap(), 0.0);
+        assert!(flat > small && flat.is_finite());
+    }
+
+    #[test]
+    fn compress_identity() {
+        let extents = super::Extents::new(24, 20);
+        let img = random_rgba8(extents);
+        let mut ctx = super::Context::default();
+
+        assert_eq!(
+            ctx.compress_identity(&img).unwrap(),
+            ctx.compress(&img, super::Swizzle::rgba()).unwrap()
+        );
+    }
+
+    #[test]
+    fn decompress_alpha_mask() {
+        let swz = super::Swizzle::rgba();
+
+        for (extents, block) in [
+            (
+                super::Extents::new(30, 18),
+                super::Extents::default_block_size(),
+            ),
+            (
+                super::Extents::new_3d(10, 7, 5),
+                super::Extents::new_3d(3, 3, 3),
+            ),
+        ] {
+            let img = random_rgba8(extents);
+
+            let mut ctx = super::Context::new(
+                super::ConfigBuilder::new()
+                    .with_block_size(super::BlockSize::try_from(block).unwrap())
+                    .build()
+                    .unwrap(),
+            )
+            .unwrap();
+            let data = ctx.compress(&img, swz).unwrap();
+
+            let mask = ctx.decompress_alpha_mask::<u8>(&data, extents).unwrap();
+            let full = ctx.decompress::<u8>(&data, extents, swz).unwrap();
+            let alpha = full
+                .data
+                .concat()
+                .chunks_exact(4)
+                .map(|texel| texel[3])
+                .collect::<Vec<u8>>();
+            assert_eq!(mask, alpha);
+        }
+    }
+
+    #[test]
+    fn content_hash() {
+        let extents = super::Extents::new(16, 16);
+        let img = random_rgba8(extents);
+        let swz = super::Swizzle::rgba();
+        let mut ctx = super::Context::default();
+
+        let compressed = |data| super::CompressedImage {
+            block: super::BlockSize::default(),
+            extents,
+            data,
+        };
+
+        let a = compressed(ctx.compress(&img, swz).unwrap());
+        let b = compressed(ctx.compress(&img, swz).unwrap());
+        let c = compressed(ctx.compress(&img, super::Swizzle::rgb1()).unwrap());
+        assert_eq!(a.content_hash(), b.content_hash());
+        assert_ne!(a.content_hash(), c.content_hash());
+
+        let mut d = a.clone();
+        d.extents = super::Extents::new(15, 16);
+        assert_ne!(a.content_hash(), d.content_hash());
+
+        assert_eq!(
+            super::CompressedImage {
+                block: super::BlockSize::default(),
+                extents: super::Extents::default(),
+                data: Vec::new(),
+            }
+            .content_hash(),
+            0x20E8_74A8_985D_4484
+        );
+    }
+
+    #[test]
+    fn decorrelate_alpha() {
+        let extents = super::Extents::new(64, 64);
+        let img = super::Image {
+            extents,
+            data: vec![(0..extents.y)
+                .flat_map(|y| {
+                    (0..extents.x).flat_map(move |x| {
+                        [
+                            (x * 4) as u8,
+                            (y * 4) as u8,
+                            ((x + y) * 2) as u8,
+                            rand::random::<u8>(),
+                        ]
+                    })
+                })
+                .collect::<Vec<u8>>()],
+        };
+        let swz = super::Swizzle::rgba();
+
+        let alpha_psnr = |builder: super::ConfigBuilder| {
+            let mut ctx = super::Context::new(builder.build().unwrap()).unwrap();
+            let data = ctx.compress(&img, swz).unwrap();
+            let decoded = ctx.decompress::<u8>(&data, extents, swz).unwrap();
+            let mse = img.data[0]
+                .chunks_exact(4)
+                .zip(decoded.data[0].chunks_exact(4))
+                .map(|(a, b)| (a[3] as f64 - b[3] as f64).powi(2))
+                .sum::<f64>()
+                / (extents.x * extents.y) as f64;
+            10.0 * (255.0 * 255.0 / mse).log10()
+        };
+
+        let plain = alpha_psnr(super::ConfigBuilder::new());
+        let decorrelated = alpha_psnr(super::ConfigBuilder::new().with_decorrelate_alpha(true));
+        assert!(decorrelated >= plain, "{} < {}", decorrelated, plain);
+    }
+
+    #[test]
+    fn all_gpu_formats() {
+        assert_eq!(super::supported_block_sizes().len(), 14 + 10);
+
+        let formats = super::all_gpu_formats();
+        assert_eq!(formats.len(), 28);
+        assert_eq!(
+            formats[0],
+            (super::Profile::LdrRgba, super::BlockSize::default(), 157)
+        );
+        assert_eq!(
+            formats[1],
+            (super::Profile::LdrSrgb, super::BlockSize::default(), 158)
+        );
+        assert_eq!(
+            formats[27],
+            (
+                super::Profile::LdrSrgb,
+                super::BlockSize::new_2d(12, 12).unwrap(),
+                184
+            )
+        );
+
+        for (profile, block, _) in formats {
+            assert!(super::ConfigBuilder::new()
+                .with_profile(profile)
+                .with_block_size(block)
+                .build()
+                .is_ok());
+        }
+    }
+
+    #[test]
+    fn crop_blocks() {
+        let full = super::Extents::new(30, 22);
+        let img = random_rgba8(full);
+        let swz = super::Swizzle::rgba();
+        let block = super::BlockSize::default();
+        let mut ctx = super::Context::default();
+
+        let data = ctx.compress(&img, swz).unwrap();
+        let decoded = ctx.decompress::<u8>(&data, full, swz).unwrap();
+
+        for (origin, size) in [
+            (super::Extents::new_3d(8, 4, 0), super::Extents::new(12, 8)),
+            (
+                super::Extents::new_3d(16, 12, 0),
+                super::Extents::new(14, 10),
+            ),
+        ] {
+            let cropped = super::crop_blocks(&data, block, full, origin, size).unwrap();
+            assert_eq!(cropped.len(), super::compressed_size(block, size));
+
+            let cropped = ctx.decompress::<u8>(&cropped, size, swz).unwrap();
+            assert_eq!(cropped.data, decoded.region(origin, size).data);
+        }
+
+        assert_eq!(
+            super::crop_blocks(
+                &data,
+                block,
+                full,
+                super::Extents::new_3d(2, 0, 0),
+                super::Extents::new(8, 8)
+            ),
+            Err(super::Error::BadParam)
+        );
+        assert_eq!(
+            super::crop_blocks(
+                &data,
+                block,
+                full,
+                super::Extents::new_3d(0, 0, 0),
+                super::Extents::new(10, 8)
+            ),
+            Err(super::Error::BadParam)
+        );
+    }
+
+    #[test]
+    fn prewarm() {
+        let extents = super::Extents::new(32, 32);
+        let img = random_rgba8(extents);
+        let swz = super::Swizzle::rgba();
+
+        let mut ctx = super::Context::default();
+        ctx.prewarm().unwrap();
+
+        // Prewarming must not leave anything behind that changes the output.
+        let data = ctx.compress(&img, swz).unwrap();
+        assert_eq!(data, super::Context::default().compress(&img, swz).unwrap());
+
+        let mut ctx = super::Context::new(
+            super::ConfigBuilder::new()
+                .with_flags(super::Flags::DECOMPRESS_ONLY)
+                .build()
+                .unwrap(),
+        )
+        .unwrap();
+        ctx.prewarm().unwrap();
+    }
+
+    #[test]
+    fn compress_mapped() {
+        struct Pixel {
+            r: u8,
+            g: u8,
+            b: u8,
+            a: u8,
+        }
+
+        let extents = super::Extents::new(20, 12);
+        let pixels = (0..extents.x * extents.y)
+            .map(|_| Pixel {
+                r: rand::random(),
+                g: rand::random(),
+                b: rand::random(),
+                a: rand::random(),
+            })
+            .collect::<Vec<_>>();
+        let swz = super::Swizzle::rgba();
+        let mut ctx = super::Context::default();
+
+        let mapped = ctx
+            .compress_mapped(&pixels, extents, |p| [p.r, p.g, p.b, p.a], swz)
+            .unwrap();
+
+        let flat = super::Image {
+            extents,
+            data: vec![pixels
+                .iter()
+                .flat_map(|p| [p.r, p.g, p.b, p.a])
+                .collect::<Vec<u8>>()],
+        };
+        assert_eq!(mapped, ctx.compress(&flat, swz).unwrap());
+
+        assert_eq!(
+            ctx.compress_mapped(&pixels[1..], extents, |p| [p.r, p.g, p.b, p.a], swz),
+            Err(super::Error::BadParam)
+        );
+    }
+
+    #[test]
+    fn decompress_rejects_mismatched_block_size() {
+        let extents = super::Extents::new(32, 32);
+        let img = random_rgba8(extents);
+        let swz = super::Swizzle::rgba();
+
+        let data = super::Context::default().compress(&img, swz).unwrap();
+
+        let mut ctx = super::Context::new(
+            super::ConfigBuilder::new()
+                .with_block_size(super::BlockSize::new_2d(8, 8).unwrap())
+                .build()
+                .unwrap(),
+        )
+        .unwrap();
+        assert_eq!(
+            ctx.decompress::<u8>(&data, extents, swz).err(),
+            Some(super::Error::BadBlockSize)
+        );
+
+        let mut out = super::Image {
+            extents,
+            data: vec![vec![0u8; (extents.x * extents.y * 4) as usize]],
+        };
+        assert_eq!(
+            ctx.decompress_into(&data, &mut out, swz),
+            Err(super::Error::BadBlockSize)
+        );
+    }
+
+    #[test]
+    fn f16_f32_conversion() {
+        let extents = super::Extents::new_3d(8, 4, 2);
+        let img = super::Image {
+            extents,
+            data: (0..extents.z)
+                .map(|_| {
+                    (0..extents.x * extents.y * 4)
+                        .map(|_| rand::random::<f32>() * 100.0)
+                        .collect::<Vec<f32>>()
+                })
+                .collect::<Vec<_>>(),
+        };
+
+        let half = img.to_f16();
+        assert_eq!(half.extents, extents);
+
+        let back = half.to_f32();
+        assert_eq!(back.extents, extents);
+        for (a, b) in img.data.concat().iter().zip(back.data.concat()) {
+            // Half-floats have a 10-bit mantissa, so the relative error is at most 2^-11, plus
+            // the spacing of the subnormals for values close to zero.
+            assert!((a - b).abs() <= a.abs() / 2048.0 + 1e-7, "{} {}", a, b);
+        }
+
+        assert_eq!(back.to_f16().data, half.data);
+    }
+
+    #[test]
+    fn compress_mip_range() {
+        let extents = super::Extents::new(64, 64);
+        let img = random_rgba8(extents);
+        let swz = super::Swizzle::rgba();
+        let block = super::BlockSize::default();
+        let mut ctx = super::Context::default();
+
+        assert_eq!(super::mip_level_count(extents), 7);
+        assert_eq!(super::mip_extents(extents, 6), super::Extents::new(1, 1));
+
+        let levels = ctx.compress_mip_range(&img, swz, 1..3).unwrap();
+        assert_eq!(
+            levels.iter().map(|(level, _)| *level).collect::<Vec<_>>(),
+            vec![1, 2]
+        );
+        assert_eq!(
+            levels[0].1.len(),
+            super::compressed_size(block, super::Extents::new(32, 32))
+        );
+        assert_eq!(
+            levels[1].1.len(),
+            super::compressed_size(block, super::Extents::new(16, 16))
+        );
+
+        let chain = ctx.compress_mips(&img, swz).unwrap();
+        assert_eq!(chain.levels.len(), 7);
+        assert_eq!(chain.levels[1], levels[0].1);
+        assert_eq!(chain.levels[2], levels[1].1);
+
+        assert!(ctx.compress_mip_range(&img, swz, 5..8).is_err());
+    }
+
+    #[test]
+    fn perceptual_active() {
+        let ctx =
+            |builder: super::ConfigBuilder| super::Context::new(builder.build().unwrap()).unwrap();
+
+        let normal =
+            ctx(super::ConfigBuilder::new().with_optimize_for(super::TextureKind::NormalMap));
+        assert!(normal.perceptual_active());
+
+        let color = ctx(super::ConfigBuilder::new().with_flags(super::Flags::USE_PERCEPTUAL));
+        assert!(!color.perceptual_active());
+
+        assert!(!super::Context::default().perceptual_active());
+    }
+
+    #[test]
+    fn ssim() {
+        let extents = super::Extents::new(32, 32);
+        let img = random_rgba8(extents);
+
+        let same = super::ssim(&img, &img).unwrap();
+        assert!((same - 1.0).abs() < 1e-9, "{}", same);
+
+        let blurred = img.downsampled().resize(extents).unwrap();
+        let blurry = super::ssim(&img, &blurred).unwrap();
+        assert!(blurry < 0.9, "{}", blurry);
+
+        let small = img.resize(super::Extents::new(16, 16)).unwrap();
+        assert_eq!(
+            super::ssim(&img, &small).err(),
+            Some(super::Error::BadParam)
+        );
+    }
+
+    #[test]
+    fn decompress_in_place() {
+        let extents = super::Extents::new(16, 12);
+        let img = random_rgba8(extents);
+        let swz = super::Swizzle::rgba();
+        let mut ctx = super::Context::default();
+        let data = ctx.compress(&img, swz).unwrap();
+
+        let mut buffer = [0u8; 16 * 12 * 4];
+        let mut out = super::Image {
+            extents,
+            data: &mut buffer[..],
+        };
+        ctx.decompress_in_place(&data, &mut out, swz).unwrap();
+
+        let decoded = ctx.decompress::<u8>(&data, extents, swz).unwrap();
+        assert_eq!(&buffer[..], &decoded.data[0][..]);
+
+        let mut short = [0u8; 16];
+        let mut out = super::Image {
+            extents,
+            data: &mut short[..],
+        };
+        assert_eq!(
+            ctx.decompress_in_place(&data, &mut out, swz),
+            Err(super::Error::BadParam)
+        );
+    }
+
+    #[test]
+    fn supported_types() {
+        for profile in [super::Profile::HdrRgba, super::Profile::HdrRgbLdrA] {
+            assert!(!super::supported_types(profile).contains(&super::Type::U8));
+            assert!(super::supported_types(profile).contains(&super::Type::F32));
+            assert!(profile.requires_float_input());
+        }
+        for profile in [super::Profile::LdrRgba, super::Profile::LdrSrgb] {
+            assert_eq!(super::supported_types(profile).len(), 3);
+        }
+
+        let extents = super::Extents::new(8, 8);
+        let img = super::Image {
+            extents,
+            data: vec![vec![128u8; (extents.x * extents.y * 4) as usize]],
+        };
+        let mut ctx = super::Context::new(
+            super::ConfigBuilder::new()
+                .with_profile(super::Profile::HdrRgba)
+                .build()
+                .unwrap(),
+        )
+        .unwrap();
+        assert_eq!(
+            ctx.compress(&img, super::Swizzle::rgba()),
+            Err(super::Error::BadParam)
+        );
+    }
+
+    #[test]
+    fn compress_subregion() {
+        let extents = super::Extents::new(40, 30);
+        let row_stride = (extents.x * 4 + 8) as usize;
+        let full = (0..row_stride * extents.y as usize)
+            .map(|_| rand::random::<u8>())
+            .collect::<Vec<u8>>();
+        let swz = super::Swizzle::rgba();
+        let mut ctx = super::Context::default();
+
+        let origin = super::Extents::new_3d(5, 7, 0);
+        let size = super::Extents::new(20, 13);
+        let data = ctx
+            .compress_subregion(&full, extents, origin, size, row_stride, swz)
+            .unwrap();
+
+        let reference = super::Image {
+            extents: size,
+            data: vec![(origin.y..origin.y + size.y)
+                .flat_map(|y| {
+                    let start = y as usize * row_stride + origin.x as usize * 4;
+                    full[start..start + size.x as usize * 4].to_vec()
+                })
+                .collect::<Vec<u8>>()],
+        };
+        assert_eq!(data, ctx.compress(&reference, swz).unwrap());
+
+        assert_eq!(
+            ctx.compress_subregion(&full, extents, origin, extents, row_stride, swz),
+            Err(super::Error::BadParam)
+        );
+    }
+
+    #[test]
+    fn describe() {
+        let ctx = super::Context::new(
+            super::ConfigBuilder::new()
+                .with_profile(super::Profile::LdrSrgb)
+                .with_block_size(super::BlockSize::new_2d(6, 5).unwrap())
+                .with_flags(super::Flags::MAP_NORMAL | super::Flags::USE_PERCEPTUAL)
+                .build()
+                .unwrap(),
+        )
+        .unwrap();
+
+        let description = ctx.describe();
+        assert!(description.contains("6x5x1"), "{}", description);
+        assert!(description.contains("LdrSrgb"), "{}", description);
+        assert!(
+            description.contains("MAP_NORMAL | USE_PERCEPTUAL"),
+            "{}",
+            description
+        );
+
+        let plain = super::Context::new(
+            super::ConfigBuilder::new()
+                .with_flags(super::Flags::empty())
+                .build()
+                .unwrap(),
+        )
+        .unwrap();
+        assert!(plain.describe().contains("flags none"));
+    }
+
+    #[test]
+    fn compress_edged() {
+        // Red on the left half and blue on the right half, with a width that isn't a multiple of
+        // the block size.
+        let extents = super::Extents::new(6, 6);
+        let img = super::Image {
+            extents,
+            data: vec![(0..extents.y)
+                .flat_map(|_| {
+                    (0..extents.x).flat_map(|x| {
+                        if x < 3 {
+                            [255, 0, 0, 255]
+                        } else {
+                            [0, 0, 255, 255]
+                        }
+                    })
+                })
+                .collect::<Vec<u8>>()],
+        };
+        let swz = super::Swizzle::rgba();
+        let mut ctx = super::Context::default();
+        let padded = super::Extents::new(8, 8);
+
+        let edge_texel = |ctx: &mut super::Context, mode| {
+            let data = ctx.compress_edged(&img, swz, mode).unwrap();
+            assert_eq!(
+                data.len(),
+                super::compressed_size(super::BlockSize::default(), extents)
+            );
+            let decoded = ctx.decompress::<u8>(&data, padded, swz).unwrap();
+            // The top right texel, which is outside the image.
+            let i = ((padded.x - 1) * 4) as usize;
+            [
+                decoded.data[0][i],
+                decoded.data[0][i + 1],
+                decoded.data[0][i + 2],
+                decoded.data[0][i + 3],
+            ]
+        };
+
+        let clamp = edge_texel(&mut ctx, super::EdgeMode::Clamp);
+        let wrap = edge_texel(&mut ctx, super::EdgeMode::Wrap);
+        let transparent = edge_texel(&mut ctx, super::EdgeMode::Transparent);
+
+        assert_ne!(clamp, wrap);
+        assert!(clamp[2] > 200 && clamp[0] < 50, "{:?}", clamp);
+        assert!(wrap[0] > 200 && wrap[2] < 50, "{:?}", wrap);
+        assert!(transparent[3] < 50, "{:?}", transparent);
+    }
+
+    #[test]
+    fn deduplicate_blocks() {
+        let a = [1u8; 16];
+        let b = super::constant_block([0; 4]);
+        let c = [7u8; 16];
+
+        let data = [a, b, a, c, b, b, a].concat();
+        let (unique, indices) = super::deduplicate_blocks(&data);
+
+        assert_eq!(unique, vec![a, b, c]);
+        assert_eq!(indices, vec![0, 1, 0, 2, 1, 1, 0]);
+
+        for (block, &index) in data.chunks_exact(16).zip(&indices) {
+            assert_eq!(block, &unique[index as usize][..]);
+        }
+    }
+
+    #[test]
+    fn multi_profile_context() {
+        let extents = super::Extents::new(16, 16);
+        let swz = super::Swizzle::rgba();
+        let mut ctx = super::MultiProfileContext::default();
+
+        let ldr = super::Image {
+            extents,
+            data: vec![vec![64u8; (extents.x * extents.y * 4) as usize]],
+        };
+        let ldr_block = super::BlockSize::new_2d(6, 6).unwrap();
+        let data = ctx
+            .compress(super::Profile::LdrRgba, ldr_block, &ldr, swz)
+            .unwrap();
+        assert_eq!(data.len(), super::compressed_size(ldr_block, extents));
+        let decoded = ctx
+            .decompress::<u8>(super::Profile::LdrRgba, ldr_block, &data, extents, swz)
+            .unwrap();
+        assert!(decoded.data[0].iter().all(|&c| c.abs_diff(64) <= 1));
+
+        let hdr = super::Image {
+            extents,
+            data: vec![vec![8.0f32; (extents.x * extents.y * 4) as usize]],
+        };
+        let hdr_block = super::BlockSize::default();
+        let data = ctx
+            .compress(super::Profile::HdrRgba, hdr_block, &hdr, swz)
+            .unwrap();
+        let decoded = ctx
+            .decompress::<f32>(super::Profile::HdrRgba, hdr_block, &data, extents, swz)
+            .unwrap();
+        assert!(decoded.data[0].iter().all(|&c| (c - 8.0).abs() < 0.1));
+
+        assert_eq!(ctx.contexts.len(), 2);
+    }
+
+    #[test]
+    fn reset_between_decompressions() {
+        let extents = super::Extents::new(16, 16);
+        let img = random_rgba8(extents);
+        let swz = super::Swizzle::rgba();
+        let data = super::Context::default().compress(&img, swz).unwrap();
+
+        let mut ctx = super::Context::new(
+            super::ConfigBuilder::new()
+                .with_flags(super::Flags::DECOMPRESS_ONLY)
+                .build()
+                .unwrap(),
+        )
+        .unwrap();
+
+        let first = ctx.decompress::<u8>(&data, extents, swz).unwrap();
+        ctx.reset().unwrap();
+        let second = ctx.decompress::<u8>(&data, extents, swz).unwrap();
+        assert_eq!(first.data, second.data);
+
+        let mut ctx = super::Context::default();
+        ctx.reset().unwrap();
+        assert_eq!(ctx.compress(&img, swz).unwrap(), data);
+    }
+
+    #[test]
+    fn channel_bounds() {
+        let img = super::Image {
+            extents: super::Extents::new_3d(2, 1, 2),
+            data: vec![
+                vec![0.5f32, 2.0, 0.0, 1.0, 16.0, 0.25, 0.0, 1.0],
+                vec![1.5f32, 3.0, 0.0, 0.5, 0.125, 8.0, 0.0, 1.0],
+            ],
+        };
+
+        assert_eq!(
+            img.channel_bounds(),
+            [[0.125, 16.0], [0.25, 8.0], [0.0, 0.0], [0.5, 1.0]]
+        );
+
+        let empty = super::Image {
+            extents: super::Extents::new(0, 0),
+            data: vec![Vec::<f32>::new()],
+        };
+        assert_eq!(
+            empty.channel_bounds()[0],
+            [f32::INFINITY, f32::NEG_INFINITY]
+        );
+    }
+
+    #[test]
+    fn block_size_rejects_illegal_footprints() {
+        assert_eq!(
+            super::BlockSize::new_2d(5, 7),
+            Err(super::Error::BadBlockSize)
+        );
+        assert_eq!(
+            super::BlockSize::new_3d(3, 3, 2),
+            Err(super::Error::BadBlockSize)
+        );
+
+        let block = super::BlockSize::new_2d(8, 6).unwrap();
+        assert_eq!(block.extents(), super::Extents::new(8, 6));
+        assert_eq!(super::BlockSize::new_3d(8, 6, 1).unwrap(), block,);
+        assert!(super::BlockSize::new_3d(4, 4, 3).is_ok());
+        assert_eq!(
+            super::BlockSize::default().extents(),
+            super::Extents::default_block_size()
+        );
+
+        for block in super::supported_block_sizes() {
+            assert_eq!(
+                super::BlockSize::try_from(super::Extents::from(block)),
+                Ok(block)
+            );
+        }
+
+        // Image extents stay unrestricted.
+        let extents = super::Extents::new(5, 7);
+        assert_eq!((extents.x, extents.y), (5, 7));
+        assert_eq!(
+            super::BlockSize::try_from(extents),
+            Err(super::Error::BadBlockSize)
+        );
+    }
+
+    #[cfg(feature = "rayon")]
+    #[test]
+    fn compress_mips_parallel_matches_serial() {
+        let extents = super::Extents::new(40, 24);
+        let img = random_rgba8(extents);
+
+        let mut ctx = super::Context::default();
+        let swz = super::Swizzle::rgba();
+
+        let serial = ctx.compress_mips(&img, swz).unwrap();
+        let parallel = ctx.compress_mips_parallel(&img, swz).unwrap();
+        assert_eq!(
+            parallel.levels.len(),
+            super::mip_level_count(extents) as usize
+        );
+        assert_eq!(parallel, serial);
+    }
+
+    #[test]
+    fn compress_to_budget_picks_smallest_fitting_block() {
+        let extents = super::Extents::new(64, 64);
+        let img = random_rgba8(extents);
+        let config = super::ConfigBuilder::new().with_preset(super::PRESET_FASTEST);
+        let swz = super::Swizzle::rgba();
+
+        let (block, data) = super::compress_to_budget(&config, &img, swz, 1 << 20).unwrap();
+        assert_eq!(block, super::BlockSize::new_2d(4, 4).unwrap());
+        assert_eq!(data.len(), 4096);
+
+        // 12x10 blocks need 672 bytes, so only 12x12 fits.
+        let (block, data) = super::compress_to_budget(&config, &img, swz, 600).unwrap();
+        assert_eq!(block, super::BlockSize::new_2d(12, 12).unwrap());
+        assert_eq!(data.len(), 576);
+
+        assert_eq!(
+            super::compress_to_budget(&config, &img, swz, 100).err(),
+            Some(super::Error::BadParam)
+        );
+    }
+
+    #[test]
+    fn is_compatible_with() {
+        let extents = super::Extents::new(24, 20);
+        let img = random_rgba8(extents);
+
+        let mut ctx = super::Context::default();
+        let data = ctx.compress(&img, super::Swizzle::rgba()).unwrap();
+        assert!(ctx.is_compatible_with(&data, extents));
+
+        let larger =
+            vec![0; super::compressed_size(super::BlockSize(super::Extents::new(8, 8)), extents)];
+        assert!(!ctx.is_compatible_with(&larger, extents));
+    }
+
+    #[test]
+    fn psnr() {
+        let extents = super::Extents::new(4, 2);
+        let a = super::Image {
+            extents,
+            data: vec![vec![0u8; 32]],
+        };
+        let b = super::Image {
+            extents,
+            data: vec![vec![255u8; 32]],
+        };
+
+        assert_eq!(super::psnr(&a, &a).unwrap(), f64::INFINITY);
+        assert!(super::psnr(&a, &b).unwrap().abs() < 1e-9);
+
+        let c = super::Image {
+            extents: super::Extents::new(8, 1),
+            data: vec![vec![0u8; 32]],
+        };
+        assert_eq!(super::psnr(&a, &c), Err(super::Error::BadParam));
+    }
+
+    #[test]
+    fn compress_preview_estimates_psnr() {
+        let extents = super::Extents::new(100, 70);
+        let img = super::Image {
+            extents,
+            data: vec![(0..extents.y)
+                .flat_map(|y| {
+                    (0..extents.x).flat_map(move |x| {
+                        [(x * 2) as u8, (y * 3) as u8, rand::random::<u8>() / 8, 255]
+                    })
+                })
+                .collect::<Vec<u8>>()],
+        };
+
+        let mut ctx = super::Context::default();
+        let swz = super::Swizzle::rgba();
+        let (data, estimate) = ctx.compress_preview(&img, swz).unwrap();
+        assert_eq!(data, ctx.compress(&img, swz).unwrap());
+
+        let decoded = ctx.decompress::<u8>(&data, extents, swz).unwrap();
+        let full = super::psnr(&img, &decoded).unwrap();
+        assert!((estimate - full).abs() < 3.0, "{} vs {}", estimate, full);
+    }
+
+    #[test]
+    fn config_try_from_builder() {
+        let builder = super::ConfigBuilder::new()
+            .with_block_size(super::BlockSize::new_2d(6, 6).unwrap())
+            .with_profile(super::Profile::LdrSrgb);
+
+        let config = super::Config::try_from(builder).unwrap();
+        assert_eq!(config.block_size(), super::Extents::new(6, 6));
+        assert!(super::Context::new(config).is_ok());
+
+        let builder = super::ConfigBuilder::new().with_plane_separation_threshold(2.0);
+        assert_eq!(
+            super::Config::try_from(builder).err(),
+            Some(super::Error::BadParam)
+        );
+    }
+
+    #[test]
+    fn plane_separation_threshold() {
+        let extents = super::Extents::new(64, 64);
+        let img = super::Image {
+            extents,
+            data: vec![(0..extents.y)
+                .flat_map(|_| {
+                    (0..extents.x).flat_map(move |x| {
+                        let color = (x * 4) as u8;
+                        [color, color, color, rand::random::<u8>()]
+                    })
+                })
+                .collect::<Vec<u8>>()],
+        };
+        let swz = super::Swizzle::rgba();
+
+        let stats = |threshold: f32| {
+            let mut ctx = super::Context::new(
+                super::ConfigBuilder::new()
+                    .with_flags(super::Flags::empty())
+                    .with_plane_separation_threshold(threshold)
+                    .build()
+                    .unwrap(),
+            )
+            .unwrap();
+            let data = ctx.compress(&img, swz).unwrap();
+            ctx.compress_stats(&data).unwrap()
+        };
+
+        let eager = stats(0.0);
+        let reluctant = stats(1.0);
+        assert_eq!(eager.blocks, 256);
+        assert_eq!(eager.error_blocks, 0);
+        assert!(eager.dual_plane_blocks > 0);
+        assert!(
+            eager.dual_plane_blocks >= reluctant.dual_plane_blocks,
+            "{:?} vs {:?}",
+            eager,
+            reluctant
+        );
+
+        assert!(super::ConfigBuilder::new()
+            .with_plane_separation_threshold(1.5)
+            .build()
+            .is_err());
+    }
+
+    #[test]
+    fn pad_to_block() {
+        let extents = super::Extents::new(130, 130);
+        let img = random_rgba8(extents);
+        let texel = |data: &[u8], width: u32, x: u32, y: u32| {
+            let i = ((y * width + x) * 4) as usize;
+            data[i..i + 4].to_vec()
+        };
+
+        let block = super::BlockSize::default();
+        let padded = img.pad_to_block(block, super::EdgeMode::Clamp).unwrap();
+        assert_eq!(padded.extents, super::Extents::new(132, 132));
+        assert_eq!(padded.data.len(), 1);
+        assert_eq!(padded.data[0].len(), 132 * 132 * 4);
+
+        let (src, dst) = (&img.data[0], &padded.data[0]);
+        assert_eq!(texel(dst, 132, 10, 20), texel(src, 130, 10, 20));
+        assert_eq!(texel(dst, 132, 131, 20), texel(src, 130, 129, 20));
+        assert_eq!(texel(dst, 132, 20, 130), texel(src, 130, 20, 129));
+        assert_eq!(texel(dst, 132, 131, 131), texel(src, 130, 129, 129));
+
+        let transparent = img
+            .pad_to_block(block, super::EdgeMode::Transparent)
+            .unwrap();
+        assert_eq!(texel(&transparent.data[0], 132, 130, 0), vec![0; 4]);
+
+        let short = super::Image {
+            extents,
+            data: vec![vec![0u8; 4]],
+        };
+        assert_eq!(
+            short.pad_to_block(block, super::EdgeMode::Clamp).err(),
+            Some(super::Error::BadParam)
+        );
+    }
+
+    #[test]
+    fn upload_chunks_cover_data() {
+        let block = super::BlockSize::default();
+        let extents = super::Extents::new_3d(40, 36, 2);
+        let compressed = super::CompressedImage {
+            block,
+            extents,
+            data: (0..super::compressed_size(block, extents))
+                .map(|i| i as u8)
+                .collect(),
+        };
+
+        let chunks = compressed.upload_chunks(4).unwrap().collect::<Vec<_>>();
+        let origins = chunks.iter().map(|&(origin, _)| origin).collect::<Vec<_>>();
+        assert_eq!(
+            origins,
+            [0, 16, 32, 0, 16, 32]
+                .iter()
+                .zip([0, 0, 0, 1, 1, 1].iter())
+                .map(|(&y, &z)| super::Extents::new_3d(0, y, z))
+                .collect::<Vec<_>>()
+        );
+
+        let row_len = 10 * 16;
+        assert_eq!(chunks[0].1.len(), 4 * row_len);
+        assert_eq!(chunks[2].1.len(), row_len);
+        assert_eq!(
+            chunks
+                .iter()
+                .flat_map(|(_, chunk)| chunk.iter().copied())
+                .collect::<Vec<u8>>(),
+            compressed.data
+        );
+
+        let mut short = compressed;
+        short.data.pop();
+        assert_eq!(short.upload_chunks(4).err(), Some(super::Error::BadParam));
+    }
+
+    #[test]
+    fn reswizzle_rgba_to_bgra() {
+        let extents = super::Extents::new(16, 12);
+        let img = super::Image {
+            extents,
+            data: vec![[200u8, 100, 30, 255].repeat((extents.x * extents.y) as usize)],
+        };
+
+        let mut ctx = super::Context::default();
+        let rgba = super::Swizzle::rgba();
+        let bgra = super::Swizzle {
+            r: super::Selector::Blue,
+            g: super::Selector::Green,
+            b: super::Selector::Red,
+            a: super::Selector::Alpha,
+        };
+
+        let data = ctx.compress(&img, rgba).unwrap();
+        let block = super::BlockSize::default();
+        let reswizzled = ctx.reswizzle(&data, block, extents, rgba, bgra).unwrap();
+        assert_eq!(reswizzled.len(), data.len());
+
+        let decoded = ctx.decompress::<u8>(&reswizzled, extents, rgba).unwrap();
+        for texel in decoded.data[0].chunks_exact(4) {
+            for (&actual, &expected) in texel.iter().zip(&[30u8, 100, 200, 255]) {
+                assert!((actual as i32 - expected as i32).abs() <= 2, "{:?}", texel);
+            }
+        }
+    }
+
+    #[test]
+    fn compress_output_matches_block_count() {
+        for &(block, extents) in &[
+            (super::Extents::new(4, 4), super::Extents::new(1, 1)),
+            (super::Extents::new(4, 4), super::Extents::new(17, 33)),
+            (super::Extents::new(6, 5), super::Extents::new(31, 9)),
+            (super::Extents::new(12, 12), super::Extents::new(64, 64)),
+            (super::Extents::new(4, 4), super::Extents::new_3d(9, 9, 3)),
+        ] {
+            let img = random_rgba8(extents);
+
+            let mut ctx = super::Context::new(
+                super::ConfigBuilder::new()
+                    .with_preset(super::PRESET_FASTEST)
+                    .with_block_size(super::BlockSize(block))
+                    .build()
+                    .unwrap(),
+            )
+            .unwrap();
+            let data = ctx.compress(&img, super::Swizzle::rgba()).unwrap();
+            assert_eq!(
+                data.len(),
+                super::compressed_size(super::BlockSize(block), extents)
+            );
+            assert!(data
+                .chunks_exact(super::Extents::block_byte_cost())
+                .all(|block| block.iter().any(|&byte| byte != 0)));
+        }
+    }
+
+    #[test]
+    fn compress_cached_hits_on_repeat() {
+        let extents = super::Extents::new(16, 16);
+        let img = random_rgba8(extents);
+
+        let mut ctx = super::Context::default();
+        let swz = super::Swizzle::rgba();
+
+        let first = ctx.compress_cached(&img, swz).unwrap();
+        assert_eq!(ctx.cache.hits, 0);
+        let second = ctx.compress_cached(&img, swz).unwrap();
+        assert_eq!(ctx.cache.hits, 1);
+        assert_eq!(first, second);
+        assert_eq!(first, ctx.compress(&img, swz).unwrap());
+
+        ctx.compress_cached(&img, super::Swizzle::rgb1()).unwrap();
+        assert_eq!(ctx.cache.hits, 1);
+
+        let mut changed = img;
+        changed.data[0][0] ^= 1;
+        ctx.compress_cached(&changed, swz).unwrap();
+        assert_eq!(ctx.cache.hits, 1);
+    }
+
+    #[test]
+    fn compress_cache_evicts_least_recently_used() {
+        let key = |i: u8| super::CacheKey([i; 32]);
+
+        let mut cache = super::CompressCache::default();
+        for i in 0..super::CompressCache::CAPACITY as u8 {
+            assert!(cache.get(&key(i)).is_none());
+            cache.insert(key(i), vec![i]);
+        }
+
+        assert_eq!(cache.get(&key(0)), Some(vec![0]));
+        assert!(cache.get(&key(100)).is_none());
+        cache.insert(key(100), vec![100]);
+
+        assert_eq!(cache.entries.len(), super::CompressCache::CAPACITY);
+        assert!(cache.entries.contains_key(&key(0)));
+        assert!(!cache.entries.contains_key(&key(1)));
+    }
+
+    #[test]
+    fn compress_cache_evicts_to_fit_max_bytes() {
+        let key = |i: u8| super::CacheKey([i; 32]);
+        let half = super::CompressCache::MAX_BYTES / 2;
+
+        let mut cache = super::CompressCache::default();
+        cache.insert(key(0), vec![0; half]);
+        cache.insert(key(1), vec![1; half]);
+        assert_eq!(cache.bytes, 2 * half);
+
+        assert!(cache.get(&key(0)).is_some());
+        cache.insert(key(2), vec![2; 1]);
+        assert!(cache.entries.contains_key(&key(0)));
+        assert!(!cache.entries.contains_key(&key(1)));
+        assert_eq!(cache.bytes, half + 1);
+
+        cache.insert(key(3), vec![3; super::CompressCache::MAX_BYTES + 1]);
+        assert!(!cache.entries.contains_key(&key(3)));
+        assert_eq!(cache.bytes, half + 1);
+    }
+
+    #[test]
+    fn compress_mips_verified_reports_psnr() {
+        let extents = super::Extents::new(64, 32);
+        let img = super::Image {
+            extents,
+            data: vec![(0..extents.y)
+                .flat_map(|y| {
+                    (0..extents.x).flat_map(move |x| [(x * 4) as u8, (y * 8) as u8, 128, 255])
+                })
+                .collect::<Vec<u8>>()],
+        };
+
+        let mut ctx = super::Context::default();
+        let swz = super::Swizzle::rgba();
+        let levels = ctx.compress_mips_verified(&img, swz).unwrap();
+
+        let chain = ctx.compress_mips(&img, swz).unwrap();
+        assert_eq!(levels.len(), chain.levels.len());
+        for ((data, quality), expected) in levels.iter().zip(&chain.levels) {
+            assert_eq!(data, expected);
+            assert!(*quality > 30.0, "{}", quality);
+        }
+    }
+
+    #[test]
+    fn compress_with_overrides_forces_constant_block() {
+        let extents = super::Extents::new(16, 16);
+        let img = random_rgba8(extents);
+
+        let mut ctx = super::Context::default();
+        let swz = super::Swizzle::rgba();
+
+        let overrides = [(5, super::BlockOverride::Constant)]
+            .iter()
+            .copied()
+            .collect::<std::collections::HashMap<_, _>>();
+        let data = ctx.compress_with_overrides(&img, swz, &overrides).unwrap();
+
+        let plain = ctx.compress(&img, swz).unwrap();
+        assert_eq!(data[..5 * 16], plain[..5 * 16]);
+        assert_eq!(data[6 * 16..], plain[6 * 16..]);
+
+        // Block 5 covers texels 4..8 in both dimensions.
+        let decoded = ctx.decompress::<u8>(&data, extents, swz).unwrap();
+        let texel = |x: u32, y: u32| {
+            let i = ((y * extents.x + x) * 4) as usize;
+            decoded.data[0][i..i + 4].to_vec()
+        };
+        for y in 4..8 {
+            for x in 4..8 {
+                assert_eq!(texel(x, y), texel(4, 4));
+            }
+        }
+
+        let out_of_range = [(16, super::BlockOverride::Constant)]
+            .iter()
+            .copied()
+            .collect::<std::collections::HashMap<_, _>>();
+        assert_eq!(
+            ctx.compress_with_overrides(&img, swz, &out_of_range).err(),
+            Some(super::Error::BadParam)
+        );
+
+        let partitions = [
+            (5, super::BlockOverride::Constant),
+            (6, super::BlockOverride::Partitions(2)),
+        ]
+        .iter()
+        .copied()
+        .collect::<std::collections::HashMap<_, _>>();
+        let data = ctx.compress_with_overrides(&img, swz, &partitions).unwrap();
+        assert_eq!(data[6 * 16..7 * 16], plain[6 * 16..7 * 16]);
+        assert_ne!(data[5 * 16..6 * 16], plain[5 * 16..6 * 16]);
+    }
+
+    #[test]
+    fn compress_with_overrides_hdr_constant_block() {
+        let extents = super::Extents::new(8, 8);
+        let img = super::Image {
+            extents,
+            data: vec![(0..extents.x * extents.y)
+                .flat_map(|_| [2.5f32, 0.25, 100.0, 1.0])
+                .collect::<Vec<f32>>()],
+        };
+
+        let mut ctx = super::Context::new(
+            super::ConfigBuilder::new()
+                .with_profile(super::Profile::HdrRgba)
+                .build()
+                .unwrap(),
+        )
+        .unwrap();
+        let swz = super::Swizzle::rgba();
+
+        let overrides = [(0, super::BlockOverride::Constant)]
+            .iter()
+            .copied()
+            .collect::<std::collections::HashMap<_, _>>();
+        let data = ctx.compress_with_overrides(&img, swz, &overrides).unwrap();
+        assert_eq!(data[1] & 0x02, 0x02);
+
+        let decoded = ctx.decompress::<f32>(&data, extents, swz).unwrap();
+        assert_eq!(decoded.data[0][..4], [2.5, 0.25, 100.0, 1.0]);
+    }
+
+    #[test]
+    fn recommended_threads() {
+        let available = std::thread::available_parallelism().map_or(1, |threads| threads.get());
+        let block = super::BlockSize::default();
+
+        let small = super::recommended_threads(
+            super::Extents::new(16, 16),
+            block,
+            super::PRESET_EXHAUSTIVE,
+        );
+        assert_eq!(small, 1);
+
+        let large = super::recommended_threads(
+            super::Extents::new(3840, 2160),
+            block,
+            super::PRESET_MEDIUM,
+        );
+        assert_eq!(large, available);
+        if available > 1 {
+            assert!(large > small);
+        }
+    }
+
+    #[test]
+    fn compress_rgbm_auto_covers_max() {
+        let extents = super::Extents::new(16, 16);
+        let img = super::Image {
+            extents,
+            data: vec![(0..extents.x * extents.y)
+                .flat_map(|i| {
+                    let value = i as f32 / 20.0;
+                    [value, value / 2.0, 0.25, 1.0]
+                })
+                .collect::<Vec<f32>>()],
+        };
+        let max = 255.0 / 20.0;
+
+        let mut ctx = super::Context::new(
+            super::ConfigBuilder::new()
+                .with_profile(super::Profile::HdrRgba)
+                .build()
+                .unwrap(),
+        )
+        .unwrap();
+        let swz = super::Swizzle::rgba();
+        let (data, scale) = ctx.compress_rgbm_auto(&img, swz).unwrap();
+        assert!(scale >= max, "{} < {}", scale, max);
+
+        let decoded = super::Context::default()
+            .decompress::<f32>(&data, extents, swz)
+            .unwrap();
+        let brightest = decoded.data[0]
+            .chunks_exact(4)
+            .map(|texel| texel[0] * texel[3] * scale)
+            .fold(0.0, f32::max);
+        assert!(
+            (brightest - max).abs() < max * 0.1,
+            "{} vs {}",
+            brightest,
+            max
+        );
+
+        // The swizzle picks the stored RGB, which still decodes as `rgb * a * scale`.
+        let swapped = super::Swizzle {
+            r: super::Selector::Green,
+            g: super::Selector::Red,
+            b: super::Selector::Blue,
+            a: super::Selector::One,
+        };
+        let (data, scale) = ctx.compress_rgbm_auto(&img, swapped).unwrap();
+        let decoded = super::Context::default()
+            .decompress::<f32>(&data, extents, swz)
+            .unwrap();
+        let brightest = decoded.data[0]
+            .chunks_exact(4)
+            .map(|texel| texel[1] * texel[3] * scale)
+            .fold(0.0, f32::max);
+        assert!(
+            (brightest - max).abs() < max * 0.1,
+            "{} vs {}",
+            brightest,
+            max
+        );
+
+        assert_eq!(
+            ctx.compress_rgbm_auto(&img, super::Swizzle::raz1()).err(),
+            Some(super::Error::BadSwizzle)
+        );
+    }
+
+    #[test]
+    fn verify_batch_flags_failures() {
+        let extents = super::Extents::new(32, 32);
+        let easy = super::Image {
+            extents,
+            data: vec![[40u8, 80, 120, 255].repeat((extents.x * extents.y) as usize)],
+        };
+        let hard = random_rgba8(extents);
+
+        let config = super::ConfigBuilder::new()
+            .with_block_size(super::BlockSize::new_2d(8, 8).unwrap())
+            .with_preset(super::PRESET_FASTEST);
+        let results = super::verify_batch(
+            &[config.clone(), config.clone()],
+            &[easy, hard],
+            super::Swizzle::rgba(),
+            35.0,
+        )
+        .unwrap();
+
+        assert_eq!(results.len(), 2);
+        assert_eq!((results[0].0, results[0].2), (0, true));
+        assert_eq!((results[1].0, results[1].2), (1, false));
+        assert!(results[0].1 > results[1].1);
+
+        assert_eq!(
+            super::verify_batch::<u8, Vec<Vec<u8>>, _>(
+                &[config],
+                &[],
+                super::Swizzle::rgba(),
+                35.0
+            ),
+            Err(super::Error::BadParam)
+        );
+    }
+
+    #[test]
+    fn diff_image() {
+        let extents = super::Extents::new(16, 8);
+        let img = random_rgba8(extents);
+
+        let same = super::diff_image(&img, &img, 8.0).unwrap();
+        assert_eq!(same.extents, extents);
+        assert!(same.data[0].iter().all(|&component| component == 0));
+
+        let mut ctx = super::Context::default();
+        let swz = super::Swizzle::rgba();
+        let data = ctx.compress(&img, swz).unwrap();
+        let decoded = ctx.decompress::<u8>(&data, extents, swz).unwrap();
+
+        let diff = super::diff_image(&img, &decoded, 8.0).unwrap();
+        assert!(diff.data[0].iter().any(|&component| component > 0));
+
+        let other = super::Image {
+            extents: super::Extents::new(8, 16),
+            data: vec![vec![0u8; 16 * 8 * 4]],
+        };
+        assert!(super::diff_image(&img, &other, 1.0).is_err());
+    }
+
+    #[cfg(feature = "memmap2")]
+    #[test]
+    fn compress_to_mmap_writes_blocks() {
+        let extents = super::Extents::new_3d(20, 12, 3);
+        let img = random_rgba8(extents);
+
+        let path = temp_path("compress-to-mmap.bin");
+
+        let mut ctx = super::Context::default();
+        let swz = super::Swizzle::rgba();
+        ctx.compress_to_mmap(&img, swz, &path).unwrap();
+
+        let file = std::fs::read(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(file, ctx.compress(&img, swz).unwrap());
+    }
+
+    #[test]
+    fn partition_histogram() {
+        let extents = super::Extents::new(64, 64);
+        let img = random_rgba8(extents);
+
+        let mut ctx = super::Context::new(
+            super::ConfigBuilder::new()
+                .with_preset(super::PRESET_THOROUGH)
+                .build()
+                .unwrap(),
+        )
+        .unwrap();
+        let data = ctx.compress(&img, super::Swizzle::rgba()).unwrap();
+        let stats = ctx.compress_stats(&data).unwrap();
+
+        let histogram = stats.partition_histogram();
+        assert_eq!(histogram.iter().sum::<u32>() as usize, stats.blocks);
+        assert!(
+            histogram[2..].iter().any(|&count| count > 0),
+            "{:?}",
+            histogram
+        );
+
+        let constant = super::constant_block([0; 4]);
+        let stats = ctx.compress_stats(&constant).unwrap();
+        assert_eq!(stats.partition_histogram(), [1, 0, 0, 0, 0]);
+    }
+
+    #[test]
+    fn compress_foreign_slice() {
+        let extents = super::Extents::new(12, 8);
+        let owned = (0..extents.x * extents.y * 4)
+            .map(|_| rand::random::<u8>())
+            .collect::<Vec<u8>>();
+
+        let foreign = super::Image {
+            extents,
+            data: vec![unsafe { super::ForeignSlice::from_raw_parts(owned.as_ptr(), owned.len()) }],
+        };
+
+        let mut ctx = super::Context::default();
+        let swz = super::Swizzle::rgba();
+        let data = ctx.compress(&foreign, swz).unwrap();
+
+        let img = super::Image {
+            extents,
+            data: vec![owned.as_slice()],
+        };
+        assert_eq!(data, ctx.compress(&img, swz).unwrap());
+    }
+
+    #[test]
+    fn recommend_swizzle() {
+        let extents = super::Extents::new(8, 8);
+        let image = |texel: fn(u8) -> [u8; 4]| super::Image {
+            extents,
+            data: vec![(0..extents.x * extents.y)
+                .flat_map(|i| texel(i as u8))
+                .collect::<Vec<u8>>()],
+        };
+
+        let grey = image(|i| [i, i, i, 255]);
+        assert_eq!(super::recommend_swizzle(&grey), super::Swizzle::rrr1());
+
+        let grey_alpha = image(|i| [i, i, i, 255 - i]);
+        assert_eq!(
+            super::recommend_swizzle(&grey_alpha),
+            super::Swizzle::rrrg()
+        );
+
+        let color = image(|i| [i, 0, 255 - i, 255]);
+        assert_eq!(super::recommend_swizzle(&color), super::Swizzle::rgb1());
+
+        let color_alpha = image(|i| [i, 0, 255 - i, i]);
+        assert_eq!(
+            super::recommend_swizzle(&color_alpha),
+            super::Swizzle::rgba()
+        );
+    }
+
+    #[test]
+    fn decompress_thumbnail_fits_max_dim() {
+        let extents = super::Extents::new(300, 130);
+        let img = super::Image {
+            extents,
+            data: vec![[90u8, 160, 30, 255].repeat((extents.x * extents.y) as usize)],
+        };
+
+        let mut ctx = super::Context::default();
+        let swz = super::Swizzle::rgba();
+        let block = super::BlockSize::default();
+        let data = ctx.compress(&img, swz).unwrap();
+
+        let thumbnail = ctx
+            .decompress_thumbnail(&data, block, extents, 32, swz)
+            .unwrap();
+        assert!(thumbnail.extents.x <= 32 && thumbnail.extents.y <= 32);
+        assert!(thumbnail.extents.x > 0 && thumbnail.extents.y > 0);
+        assert_eq!(
+            thumbnail.data[0].len(),
+            (thumbnail.extents.x * thumbnail.extents.y * 4) as usize
+        );
+        for texel in thumbnail.data[0].chunks_exact(4) {
+            for (&actual, &expected) in texel.iter().zip(&[90u8, 160, 30, 255]) {
+                assert!((actual as i32 - expected as i32).abs() <= 2, "{:?}", texel);
+            }
+        }
+
+        let full = ctx
+            .decompress_thumbnail(&data, block, extents, 300, swz)
+            .unwrap();
+        assert_eq!(full.extents, extents);
+    }
+
+    #[test]
+    fn no_alpha_weight() {
+        let config = super::ConfigBuilder::new().build().unwrap();
+        assert!(config
+            .builder
+            .flags
+            .contains(super::Flags::USE_ALPHA_WEIGHT));
+
+        let config = super::ConfigBuilder::new()
+            .with_flags(super::Flags::USE_ALPHA_WEIGHT | super::Flags::USE_PERCEPTUAL)
+            .with_no_alpha_weight()
+            .build()
+            .unwrap();
+        assert_eq!(config.builder.flags, super::Flags::USE_PERCEPTUAL);
+        assert_eq!(
+            config.inner.flags & super::Flags::USE_ALPHA_WEIGHT.into_sys(),
+            0
+        );
+    }
+
+    #[test]
+    fn decompress_clamped() {
+        let extents = super::Extents::new(16, 12);
+        let hdr = super::Image {
+            extents,
+            data: vec![(0..extents.x * extents.y * 4)
+                .map(|i| {
+                    if i % 4 == 3 {
+                        1.0
+                    } else {
+                        (i % 13) as f32 * 0.5
+                    }
+                })
+                .collect::<Vec<f32>>()],
+        };
+
+        let mut ctx = super::Context::new(
+            super::ConfigBuilder::new()
+                .with_profile(super::Profile::HdrRgba)
+                .build()
+                .unwrap(),
+        )
+        .unwrap();
+        let swz = super::Swizzle::rgba();
+        let data = ctx.compress(&hdr, swz).unwrap();
+
+        let full = ctx.decompress::<f32>(&data, extents, swz).unwrap();
+        assert!(full.data[0].iter().any(|&c| c > 1.0));
+
+        let clamped = ctx
+            .decompress_clamped(&data, extents, swz, 0.0, 1.0)
+            .unwrap();
+        assert_eq!(clamped.extents, extents);
+        for (&clamped, &full) in clamped.data[0].iter().zip(&full.data[0]) {
+            assert!((0.0..=1.0).contains(&clamped));
+            assert_eq!(clamped, full.clamp(0.0, 1.0));
+        }
+
+        assert_eq!(
+            ctx.decompress_clamped(&data, extents, swz, 1.0, 0.0).err(),
+            Some(super::Error::BadParam)
+        );
+    }
+
+    #[test]
+    fn active_tuning_fields() {
+        let fields = super::Config::default().active_tuning_fields();
+        assert!(!fields.is_empty());
+
+        let candidate_limit = fields
+            .iter()
+            .find(|(name, _)| *name == "tune_candidate_limit")
+            .map(|&(_, value)| value)
+            .unwrap();
+        assert!(candidate_limit >= 1.0);
+
+        let config = super::ConfigBuilder::new()
+            .with_refinement_iterations(7)
+            .build()
+            .unwrap();
+        assert!(config
+            .active_tuning_fields()
+            .contains(&("tune_refinement_limit", 7.0)));
+    }
+
+    #[test]
+    fn decompress_to_writer() {
+        let swz = super::Swizzle::rgba();
+        for (block, extents) in [
+            (
+                super::BlockSize::new_2d(6, 5).unwrap(),
+                super::Extents::new(40, 27),
+            ),
+            (
+                super::BlockSize::new_3d(4, 4, 4).unwrap(),
+                super::Extents::new_3d(10, 9, 6),
+            ),
+        ] {
+            let img = random_rgba8(extents);
+
+            let mut ctx = super::Context::new(
+                super::ConfigBuilder::new()
+                    .with_block_size(block)
+                    .build()
+                    .unwrap(),
+            )
+            .unwrap();
+            let data = ctx.compress(&img, swz).unwrap();
+
+            let mut cursor = std::io::Cursor::new(Vec::new());
+            ctx.decompress_to_writer::<u8, _>(&data, block, extents, swz, &mut cursor)
+                .unwrap();
+
+            let full = ctx.decompress::<u8>(&data, extents, swz).unwrap();
+            assert_eq!(cursor.into_inner(), full.data.concat());
+        }
+
+        let mut ctx = super::Context::default();
+        assert_eq!(
+            ctx.decompress_to_writer::<u8, _>(
+                &[0; 16],
+                super::BlockSize::new_2d(6, 6).unwrap(),
+                super::Extents::new(4, 4),
+                super::Swizzle::rgba(),
+                std::io::sink(),
+            ),
+            Err(super::Error::BadBlockSize)
+        );
+    }
+
+    #[test]
+    fn block_size_math() {
+        let block = super::BlockSize::new_2d(6, 5).unwrap();
+        let extents = super::Extents::new(40, 27);
+        assert_eq!(block.grid(extents), super::Extents::new(7, 6));
+        assert_eq!(block.compressed_size(extents), 7 * 6 * 16);
+        assert_eq!(
+            block.compressed_size(extents),
+            super::compressed_size(block, extents)
+        );
+
+        let block_3d = super::BlockSize::new_3d(4, 4, 4).unwrap();
+        assert_eq!(
+            block_3d.grid(super::Extents::new_3d(9, 8, 5)),
+            super::Extents::new_3d(3, 2, 2)
+        );
+
+        let mut ctx = super::Context::new(
+            super::ConfigBuilder::new()
+                .with_block_size(block)
+                .build()
+                .unwrap(),
+        )
+        .unwrap();
+        assert_eq!(ctx.block_size(), block);
+
+        let img = super::Image {
+            extents,
+            data: vec![vec![0u8; (extents.x * extents.y * 4) as usize]],
+        };
+        let data = ctx.compress(&img, super::Swizzle::rgba()).unwrap();
+        assert_eq!(data.len(), ctx.block_size().compressed_size(extents));
+    }
+
+    #[test]
+    fn compress_with_preview() {
+        let extents = super::Extents::new(64, 44);
+        let img = super::Image {
+            extents,
+            data: vec![(0..extents.x * extents.y)
+                .flat_map(|i| {
+                    let (x, y) = (i % extents.x, i / extents.x);
+                    [(x * 4) as u8, (y * 4) as u8, 128, 255]
+                })
+                .collect::<Vec<u8>>()],
+        };
+        let swz = super::Swizzle::rgba();
+        let mut ctx = super::Context::default();
+
+        let (data, preview) = ctx.compress_with_preview(&img, swz).unwrap();
+        assert_eq!(preview.extents, super::Extents::new(8, 6));
+        assert_eq!(preview.data[0].len(), 8 * 6 * 4);
+
+        // The first box averages x and y in 0..8, and the last row only covers y in 40..44.
+        assert_eq!(preview.data[0][..4], [14, 14, 128, 255]);
+        let last = (8 * 5 + 7) * 4;
+        assert_eq!(preview.data[0][last..last + 4], [238, 166, 128, 255]);
+
+        assert_eq!(data, ctx.compress(&img, swz).unwrap());
+        let decoded = ctx.decompress::<u8>(&data, extents, swz).unwrap();
+        assert_eq!(decoded.extents, extents);
+        assert!(region_psnr(&img.data[0], &decoded.data[0], extents.x, (0, 0), 32) > 30.0);
+    }
+
+    #[test]
+    fn set_perceptual() {
+        let extents = super::Extents::new(32, 32);
+        let img = super::Image {
+            extents,
+            data: vec![(0..extents.x * extents.y)
+                .flat_map(|_| {
+                    let x = rand::random::<u8>() / 2 + 64;
+                    let y = rand::random::<u8>() / 2 + 64;
+                    [x, y, 0, 255]
+                })
+                .collect::<Vec<u8>>()],
+        };
+        let swz = super::Swizzle::rrrg();
+
+        let mut ctx = super::Context::new(
+            super::ConfigBuilder::new()
+                .with_optimize_for(super::TextureKind::NormalMap)
+                .build()
+                .unwrap(),
+        )
+        .unwrap();
+        assert!(ctx.perceptual_active());
+        let perceptual = ctx.compress(&img, swz).unwrap();
+        ctx.compress_cached(&img, swz).unwrap();
+
+        ctx.set_perceptual(false).unwrap();
+        assert!(!ctx.perceptual_active());
+        assert!(ctx.config.builder.flags.contains(super::Flags::MAP_NORMAL));
+        assert!(ctx.cache.entries.is_empty());
+        let plain = ctx.compress(&img, swz).unwrap();
+        assert_ne!(perceptual, plain);
+
+        ctx.set_perceptual(false).unwrap();
+        ctx.set_perceptual(true).unwrap();
+        assert_eq!(ctx.compress(&img, swz).unwrap(), perceptual);
+    }
 }
@@ -0,0 +1,94 @@
+//! Helpers for testing code that uses this crate, enabled with the `test-utils` feature.
+
+use std::ops::Deref;
+
+use crate::{Context, DataType, Image, Selector, Swizzle};
+
+/// Compress `image` with `swizzle`, decompress it again and panic if any component differs from
+/// the swizzled source by more than `max_err`. Components are compared as floats using
+/// `DataType::to_f32`, so for `u8` images the error is relative to a full range of `1.0`.
+pub fn assert_roundtrip<D, T, L>(
+    ctx: &mut Context,
+    image: &Image<T>,
+    swizzle: Swizzle,
+    max_err: f64,
+) where
+    D: DataType + Copy,
+    T: Deref<Target = [L]>,
+    L: Deref<Target = [D]>,
+{
+    let data = ctx
+        .compress(image, swizzle)
+        .expect("compressing the image failed");
+    let decoded = ctx
+        .decompress::<D>(&data, image.extents, Swizzle::rgba())
+        .expect("decompressing the image failed");
+
+    let select = |texel: &[D], selector: Selector| match selector {
+        Selector::Red => texel[0].to_f32(),
+        Selector::Green => texel[1].to_f32(),
+        Selector::Blue => texel[2].to_f32(),
+        Selector::Alpha => texel[3].to_f32(),
+        Selector::Zero => 0.0,
+        Selector::One => 1.0,
+        Selector::Z => panic!("`Selector::Z` can only be used for decompression"),
+    };
+
+    for (z, (source, decoded)) in image.data.iter().zip(&decoded.data).enumerate() {
+        for (i, (source, decoded)) in source
+            .chunks_exact(4)
+            .zip(decoded.chunks_exact(4))
+            .enumerate()
+        {
+            let expected = [swizzle.r, swizzle.g, swizzle.b, swizzle.a]
+                .map(|selector| select(source, selector));
+
+            for (component, (&expected, actual)) in expected.iter().zip(decoded).enumerate() {
+                let error = (expected as f64 - actual.to_f32() as f64).abs();
+                assert!(
+                    error <= max_err,
+                    "component {} of texel ({}, {}, {}) is off by {} (maximum {})",
+                    component,
+                    i as u32 % image.extents.x,
+                    i as u32 / image.extents.x,
+                    z,
+                    error,
+                    max_err,
+                );
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{Context, Extents, Image, Swizzle};
+
+    #[test]
+    fn assert_roundtrip_accepts_gradient() {
+        let extents = Extents::new(64, 32);
+        let data = (0..extents.y)
+            .flat_map(|y| {
+                (0..extents.x).flat_map(move |x| [(x * 4) as u8, (y * 8) as u8, 128, 255])
+            })
+            .collect::<Vec<u8>>();
+        let layers = [data.as_slice()];
+        let img = Image {
+            extents,
+            data: &layers[..],
+        };
+
+        let mut ctx = Context::default();
+        super::assert_roundtrip(&mut ctx, &img, Swizzle::rgba(), 0.05);
+        super::assert_roundtrip(&mut ctx, &img, Swizzle::rgb1(), 0.05);
+    }
+
+    #[test]
+    #[should_panic(expected = "is off by")]
+    fn assert_roundtrip_rejects_large_error() {
+        let extents = Extents::new(16, 16);
+        let img = crate::tests::random_rgba8(extents);
+
+        super::assert_roundtrip(&mut Context::default(), &img, Swizzle::rgba(), 0.0);
+    }
+}